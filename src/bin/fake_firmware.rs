@@ -0,0 +1,32 @@
+extern crate fssp_simulator;
+
+use fssp_simulator::firmware::Firmware;
+use std::env;
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+const DEFAULT_DRIVER_ADDRESS: &str = "localhost:16000";
+const DEFAULT_SENSOR_ADDRESS: &str = "localhost:16001";
+const PRINT_PERIOD_MS: u64 = 1_000;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let driver = args.get(1).map(|arg| arg.as_str()).unwrap_or(DEFAULT_DRIVER_ADDRESS);
+    let sensor = args.get(2).map(|arg| arg.as_str()).unwrap_or(DEFAULT_SENSOR_ADDRESS);
+
+    let firmware = match Firmware::bind(driver, sensor) {
+        Ok(firmware) => firmware,
+        Err(error) => {
+            println!(":: {}", error);
+            process::exit(1);
+        }
+    };
+    println!(":: Driver on {}, sensor on {}", driver, sensor);
+
+    let machine = firmware.start();
+    loop {
+        thread::sleep(Duration::from_millis(PRINT_PERIOD_MS));
+        println!("{}", machine.lock().unwrap().status());
+    }
+}
@@ -0,0 +1,273 @@
+use driver::command::DEFAULT_PRECISION;
+use error::{Context, Error, Result};
+use gilrs::Button;
+use mode;
+use planner::Limits;
+use std::fs::File;
+use std::io::Read;
+use toml;
+use units::{Millimeters, MillimetersPerMinute, Newtons};
+
+/// Settings read from a TOML file, every missing value taking its default.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub driver: Driver,
+    pub sensor: Sensor,
+    pub manual: Manual,
+    pub planner: Planner,
+    pub tension: Tension,
+    pub homing: Homing,
+    pub watchdog: Watchdog,
+    pub heartbeat: Heartbeat,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Driver {
+    /// Decimals of the coordinates sent to the firmware.
+    pub precision: usize,
+    pub transport: Transport,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Sensor {
+    pub transport: Transport,
+}
+
+/// How a link reaches the firmware, see `transport`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Transport {
+    Tcp { address: String },
+    Unix { path: String },
+    /// Serial port, e.g. `/dev/ttyACM0`, in raw mode.
+    Serial { path: String, baud: u32 },
+    /// Written for the driver, truncating it, read for the sensor.
+    File { path: String },
+    /// Driver only, the commands are printed.
+    Stdout,
+    /// Sensor only.
+    Stdin,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Manual {
+    /// Jog with relative moves (G91) rather than absolute positions.
+    pub relative: bool,
+    /// Wait for the position reported by the firmware before moving.
+    pub sync: bool,
+    /// File the taught waypoints are exported to, as a G-code program.
+    pub program: String,
+}
+
+/// Limits applied to the motions planned on the host.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Planner {
+    /// In mm/s² and mm/s³.
+    pub translation: Limits,
+    /// In deg/s² and deg/s³.
+    pub rotation: Limits,
+}
+
+/// Balancing of the cable tensions, see `mode::tension`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Tension {
+    /// Pre-tension every cable should reach.
+    pub target: Newtons,
+    /// Distance to the target that is good enough.
+    pub tolerance: Newtons,
+    /// Tension at which the balancing is aborted.
+    pub overload: Newtons,
+    /// Cable length wound in per newton missing, in mm/N.
+    pub compliance: f32,
+    /// Longest adjustment for one measurement.
+    pub step: Millimeters,
+    pub speed: MillimetersPerMinute,
+    /// Seconds left for the readings to settle once an adjustment is done.
+    pub settle: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Homing {
+    /// Motion is only allowed once the machine is homed, unless overridden.
+    pub required: bool,
+    /// Longest wait for the firmware during the homing, in seconds.
+    pub timeout: f32,
+    /// Position reported once homed, X, Y and Z in mm then U and V in degrees.
+    pub position: [f32; 5],
+    /// Largest distance to `position` once homed, in mm or degrees.
+    pub tolerance: f32,
+}
+
+/// Dead-man watchdog on the controls, see `mode::watchdog`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Watchdog {
+    /// Seconds without any control before the sticks are released, 0 to
+    /// never release them. Off by default, as a stick held still sends
+    /// nothing.
+    pub timeout: f32,
+    /// Button to hold down for the controls to move the machine, one that no
+    /// mode acts on.
+    pub hold: Option<Button>,
+}
+
+/// Heartbeats between the host and the firmware, see `mode::heartbeat`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Heartbeat {
+    /// Seconds between the heartbeats sent to the firmware, 0 for none.
+    pub period: f32,
+    /// Seconds without any line from the firmware before `action` is taken,
+    /// 0 to never take it.
+    pub timeout: f32,
+    pub action: Action,
+}
+
+/// What to do when the firmware goes quiet.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// Only tell the operator.
+    Warn,
+    /// Release the sticks and stop ticking the mode until the firmware is back.
+    Pause,
+    /// Halt the machine, which has to be homed again.
+    Stop,
+}
+
+impl Default for Driver {
+    fn default() -> Driver {
+        Driver {
+            precision: DEFAULT_PRECISION,
+            transport: Transport::Tcp {
+                address: String::from("localhost:16000"),
+            },
+        }
+    }
+}
+
+impl Default for Sensor {
+    fn default() -> Sensor {
+        Sensor {
+            transport: Transport::Tcp {
+                address: String::from("localhost:16001"),
+            },
+        }
+    }
+}
+
+impl Default for Manual {
+    fn default() -> Manual {
+        Manual {
+            relative: false,
+            sync: false,
+            program: String::from("waypoints.gcode"),
+        }
+    }
+}
+
+impl Default for Planner {
+    fn default() -> Planner {
+        Planner {
+            translation: Limits {
+                acceleration: 500.,
+                jerk: 5_000.,
+            },
+            rotation: Limits {
+                acceleration: 200.,
+                jerk: 2_000.,
+            },
+        }
+    }
+}
+
+impl Default for Tension {
+    fn default() -> Tension {
+        Tension {
+            target: Newtons(20.),
+            tolerance: Newtons(1.),
+            overload: Newtons(100.),
+            compliance: 0.5,
+            step: Millimeters(2.),
+            speed: MillimetersPerMinute(300.),
+            settle: 0.5,
+        }
+    }
+}
+
+impl Default for Homing {
+    fn default() -> Homing {
+        Homing {
+            required: true,
+            timeout: 60.,
+            position: [0.; 5],
+            tolerance: 0.01,
+        }
+    }
+}
+
+impl Default for Watchdog {
+    fn default() -> Watchdog {
+        Watchdog {
+            timeout: 0.,
+            hold: None,
+        }
+    }
+}
+
+impl Default for Heartbeat {
+    fn default() -> Heartbeat {
+        Heartbeat {
+            period: 0.5,
+            timeout: 2.,
+            action: Action::Pause,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &str) -> Result<Config> {
+        let mut content = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut content))
+            .context("reading configuration")?;
+        Config::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> Result<Config> {
+        let config: Config = toml::from_str(content).map_err(|error| Error::Decoding {
+            context: format!("configuration: {}", error),
+        })?;
+        check_limits(&config.planner.translation, "planner.translation")?;
+        check_limits(&config.planner.rotation, "planner.rotation")?;
+        check_hold(config.watchdog.hold)?;
+        Ok(config)
+    }
+}
+
+/// The planner divides by both limits, and would crawl forever with none.
+fn check_limits(limits: &Limits, section: &str) -> Result<()> {
+    if limits.acceleration > 0. && limits.jerk > 0. {
+        return Ok(());
+    }
+    Err(Error::Decoding {
+        context: format!("configuration: {} limits must be positive", section),
+    })
+}
+
+/// The watchdog keeps the hold-to-move button from the modes.
+fn check_hold(hold: Option<Button>) -> Result<()> {
+    match hold {
+        Some(button) if mode::BUTTONS.contains(&button) => Err(Error::Decoding {
+            context: format!("configuration: watchdog.hold {:?} is already used by the modes", button),
+        }),
+        _ => Ok(()),
+    }
+}
@@ -1,8 +1,37 @@
-use gilrs::Event;
+use gilrs;
+use gilrs::{Axis, Button};
 
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Control {
-    Joystick { event: Event },
+    Joystick { event: Joystick },
     Keyboard { keycode: i32 },
 }
+
+/// Gamepad event stripped of its platform specific parts, so that it can be
+/// recorded and replayed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Joystick {
+    ButtonPressed(Button),
+    ButtonReleased(Button),
+    ButtonChanged(Button, f32),
+    AxisChanged(Axis, f32),
+    Connected,
+    Disconnected,
+}
+
+impl Joystick {
+    pub fn from_gilrs(event: gilrs::EventType) -> Option<Joystick> {
+        match event {
+            gilrs::EventType::ButtonPressed(button, _) => Some(Joystick::ButtonPressed(button)),
+            gilrs::EventType::ButtonReleased(button, _) => Some(Joystick::ButtonReleased(button)),
+            gilrs::EventType::ButtonChanged(button, value, _) => {
+                Some(Joystick::ButtonChanged(button, value))
+            }
+            gilrs::EventType::AxisChanged(axis, value, _) => Some(Joystick::AxisChanged(axis, value)),
+            gilrs::EventType::Connected => Some(Joystick::Connected),
+            gilrs::EventType::Disconnected => Some(Joystick::Disconnected),
+            _ => None,
+        }
+    }
+}
@@ -1,31 +1,133 @@
-use controller::control::Control;
+use controller::control::{Control, Joystick};
+use error::{Context, Error, Result};
 use gilrs::Gilrs;
+use libc;
+use message::Message;
 use ncurses;
-use std::sync::mpsc;
+use std::fs::OpenOptions;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
 use std::{thread, time};
 
 pub mod control;
 
-const WAIT_DURATION_MS: u64 = 1;
+// gilrs has no blocking API, so the joystick is the only input still polled
+const JOYSTICK_POLL_MS: u64 = 10;
+// Upper bound on how long the keyboard thread takes to notice a shutdown
+const KEYBOARD_TIMEOUT_MS: i32 = 100;
 
-pub fn connect_controller() -> mpsc::Receiver<Control> {
-    let (tx, rx) = mpsc::channel();
+/// Controlling terminal of the operator.
+pub const TERMINAL: &str = "/dev/tty";
 
-    thread::spawn(move || listen(tx));
+pub struct Link {
+    running: Arc<AtomicBool>,
+    threads: Vec<thread::JoinHandle<()>>,
+}
 
-    return rx;
+impl Link {
+    pub fn close(self) {
+        self.running.store(false, Ordering::SeqCst);
+        for thread in self.threads {
+            thread.join().ok();
+        }
+    }
 }
 
-fn listen(tx: mpsc::Sender<Control>) {
-    let wait_duration = time::Duration::from_millis(WAIT_DURATION_MS);
-    let mut gilrs = Gilrs::new().unwrap();
-    loop {
-        if let Some(event) = gilrs.next_event() {
-            tx.send(Control::Joystick { event: event }).unwrap();
+/// Move the keyboard, ncurses and the operator messages to the terminal at
+/// `path`, usually `TERMINAL`, leaving stdin and stdout to the links
+/// connected beforehand, which hold their own copies of them.
+pub fn take_terminal(path: &str) -> Result<()> {
+    let terminal = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .context("opening terminal")?;
+    for &fd in [libc::STDIN_FILENO, libc::STDOUT_FILENO].iter() {
+        if unsafe { libc::dup2(terminal.as_raw_fd(), fd) } < 0 {
+            return Err(io::Error::last_os_error()).context("taking terminal");
         }
+    }
+    Ok(())
+}
+
+/// Listen to both the keyboard and the gamepads. Fails if gamepads are not
+/// supported, in which case `connect_keyboard` can be used instead.
+pub fn connect_controller(tx: &mpsc::Sender<Message>) -> Result<Link> {
+    let mut link = connect_keyboard(tx);
+    let (init_tx, init_rx) = mpsc::channel();
+
+    let joystick = tx.clone();
+    let flag = link.running.clone();
+    link.threads.push(thread::spawn(move || listen_joystick(joystick, flag, init_tx)));
+
+    let init = init_rx.recv().unwrap_or(Err(Error::Gamepad {
+        context: String::from("joystick thread died"),
+    }));
+    match init {
+        Ok(()) => Ok(link),
+        Err(error) => {
+            link.close();
+            Err(error)
+        }
+    }
+}
+
+pub fn connect_keyboard(tx: &mpsc::Sender<Message>) -> Link {
+    let running = Arc::new(AtomicBool::new(true));
+
+    let keyboard = tx.clone();
+    let flag = running.clone();
+    let thread = thread::spawn(move || listen_keyboard(keyboard, flag));
+
+    return Link {
+        running: running,
+        threads: vec![thread],
+    };
+}
+
+fn listen_keyboard(tx: mpsc::Sender<Message>, running: Arc<AtomicBool>) {
+    // Blocks until a key is pressed or the timeout expires
+    ncurses::timeout(KEYBOARD_TIMEOUT_MS);
+    while running.load(Ordering::SeqCst) {
         let ch = ncurses::getch();
-        if ch != -1 {
-            tx.send(Control::Keyboard { keycode: ch }).unwrap();
+        if ch == ncurses::ERR {
+            continue;
+        }
+        if tx.send(Message::Control(Control::Keyboard { keycode: ch })).is_err() {
+            break;
+        }
+    }
+}
+
+fn listen_joystick(
+    tx: mpsc::Sender<Message>,
+    running: Arc<AtomicBool>,
+    init: mpsc::Sender<Result<()>>,
+) {
+    let wait_duration = time::Duration::from_millis(JOYSTICK_POLL_MS);
+    // Gilrs is not Send, so it has to be created on the thread using it
+    let mut gilrs = match Gilrs::new() {
+        Ok(gilrs) => gilrs,
+        Err(error) => {
+            init.send(Err(Error::Gamepad {
+                context: format!("{}", error),
+            }))
+            .ok();
+            return;
+        }
+    };
+    init.send(Ok(())).ok();
+    while running.load(Ordering::SeqCst) {
+        while let Some(event) = gilrs.next_event() {
+            let event = match Joystick::from_gilrs(event.event) {
+                Some(event) => event,
+                None => continue,
+            };
+            if tx.send(Message::Control(Control::Joystick { event: event })).is_err() {
+                return;
+            }
         }
         thread::sleep(wait_duration);
     }
@@ -1,121 +1,252 @@
-pub type Num = i32;
+use error::{Error, Result};
+use frame::{Machine, Vector};
+use units::{Degrees, DegreesPerMinute, Millimeters, MillimetersPerMinute};
 
-#[derive(Debug, PartialEq)]
+/// Bare number, as written in the G-code.
+pub type Num = f32;
+
+/// Decimals written in the G-code unless configured otherwise.
+pub const DEFAULT_PRECISION: usize = 3;
+
+/// Axis of the machine, named by its G-code letter.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+    U,
+    V,
+}
+
+/// Every axis, e.g. for homing.
+pub const AXES: [Axis; 5] = [Axis::X, Axis::Y, Axis::Z, Axis::U, Axis::V];
+
+impl Axis {
+    pub fn letter(self) -> char {
+        match self {
+            Axis::X => 'X',
+            Axis::Y => 'Y',
+            Axis::Z => 'Z',
+            Axis::U => 'U',
+            Axis::V => 'V',
+        }
+    }
+
+    pub fn from_letter(letter: char) -> Option<Axis> {
+        match letter {
+            'X' => Some(Axis::X),
+            'Y' => Some(Axis::Y),
+            'Z' => Some(Axis::Z),
+            'U' => Some(Axis::U),
+            'V' => Some(Axis::V),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[allow(dead_code)]
 pub enum Command {
     MoveTo {
-        x: Option<Num>,
-        y: Option<Num>,
-        z: Option<Num>,
-        f: Option<Num>,
+        x: Option<Millimeters>,
+        y: Option<Millimeters>,
+        z: Option<Millimeters>,
+        f: Option<MillimetersPerMinute>,
     },
     RotateTo {
-        u: Option<Num>,
-        v: Option<Num>,
-        f: Option<Num>,
+        u: Option<Degrees>,
+        v: Option<Degrees>,
+        f: Option<DegreesPerMinute>,
+    },
+    /// Arc in the XY plane around the center at `i`, `j` from the start.
+    ArcTo {
+        clockwise: bool,
+        x: Option<Millimeters>,
+        y: Option<Millimeters>,
+        z: Option<Millimeters>,
+        i: Millimeters,
+        j: Millimeters,
+        f: Option<MillimetersPerMinute>,
     },
     MoveMotorTo {
-        m: Num,
-        l: Num,
-        f: Option<Num>,
+        m: usize,
+        l: Millimeters,
+        f: Option<MillimetersPerMinute>,
+    },
+    /// Home the given axes, all of them when empty.
+    MoveToHome {
+        axes: Vec<Axis>,
     },
-    MoveToHome,
     NoOp,
+    /// `p` in milliseconds, `s` in seconds.
     Pause {
         p: Option<Num>,
         s: Option<Num>,
     },
+    /// Ask for the current position, answered on the driver link.
+    QueryPosition,
+    EnableMotors,
+    DisableMotors,
+    /// Sent periodically to show the host is alive, the firmware halts when
+    /// they stop coming.
+    Heartbeat,
     SetAbsolute,
     SetAttachPosition {
-        n: Num,
-        x: Num,
-        y: Num,
-        z: Num,
+        n: usize,
+        x: Millimeters,
+        y: Millimeters,
+        z: Millimeters,
     },
     SetPosition {
-        x: Option<Num>,
-        y: Option<Num>,
-        z: Option<Num>,
+        x: Option<Millimeters>,
+        y: Option<Millimeters>,
+        z: Option<Millimeters>,
     },
     SetMotorZero {
-        m: Num,
+        m: usize,
+    },
+    /// Scale the feed rates of the next moves, in percent.
+    SetFeedOverride {
+        s: Num,
     },
     SetRelative,
     Shutdown,
 }
 
+impl Command {
+    pub fn move_to(position: Vector<Machine, Millimeters>, feed: MillimetersPerMinute) -> Command {
+        Command::MoveTo {
+            x: Some(position.x),
+            y: Some(position.y),
+            z: Some(position.z),
+            f: Some(feed),
+        }
+    }
+
+    /// Whether the command makes the machine move.
+    pub fn is_motion(&self) -> bool {
+        match *self {
+            Command::MoveTo { .. }
+            | Command::RotateTo { .. }
+            | Command::ArcTo { .. }
+            | Command::MoveMotorTo { .. }
+            | Command::MoveToHome { .. } => true,
+            _ => false,
+        }
+    }
+}
+
 pub trait GCode {
-    fn to_gcode(&self) -> String;
+    /// Encode with `precision` decimals at most.
+    fn to_gcode(&self, precision: usize) -> Result<String>;
 }
 
 impl GCode for Command {
-    fn to_gcode(&self) -> String {
-        match *self {
+    fn to_gcode(&self, precision: usize) -> Result<String> {
+        let num = |value: Num| number(value, precision);
+        let code = match *self {
             Command::MoveTo { x, y, z, f } => {
                 let mut params = String::new();
                 match x {
                     None => (),
-                    Some(val) => params.push_str(&format!("X{} ", val)),
+                    Some(val) => params.push_str(&format!("X{} ", num(val.0))),
                 }
                 match y {
                     None => (),
-                    Some(val) => params.push_str(&format!("Y{} ", val)),
+                    Some(val) => params.push_str(&format!("Y{} ", num(val.0))),
                 }
                 match z {
                     None => (),
-                    Some(val) => params.push_str(&format!("Z{} ", val)),
+                    Some(val) => params.push_str(&format!("Z{} ", num(val.0))),
                 }
                 match f {
                     None => format!("G0 {}", params),
-                    Some(val) => format!("G1 {}F{}", params, val),
+                    Some(val) => format!("G1 {}F{}", params, num(val.0)),
                 }
             }
             Command::RotateTo { u, v, f } => {
                 let mut params = String::new();
                 match u {
                     None => (),
-                    Some(val) => params.push_str(&format!("U{} ", val)),
+                    Some(val) => params.push_str(&format!("U{} ", num(val.0))),
                 }
                 match v {
                     None => (),
-                    Some(val) => params.push_str(&format!("V{} ", val)),
+                    Some(val) => params.push_str(&format!("V{} ", num(val.0))),
                 }
                 match f {
                     None => format!("G0 {}", params),
-                    Some(val) => format!("G1 {}F{}", params, val),
+                    Some(val) => format!("G1 {}F{}", params, num(val.0)),
                 }
             }
+            Command::ArcTo { clockwise, x, y, z, i, j, f } => {
+                let mut params = String::new();
+                match x {
+                    None => (),
+                    Some(val) => params.push_str(&format!("X{} ", num(val.0))),
+                }
+                match y {
+                    None => (),
+                    Some(val) => params.push_str(&format!("Y{} ", num(val.0))),
+                }
+                match z {
+                    None => (),
+                    Some(val) => params.push_str(&format!("Z{} ", num(val.0))),
+                }
+                params.push_str(&format!("I{} J{}", num(i.0), num(j.0)));
+                match f {
+                    None => (),
+                    Some(val) => params.push_str(&format!(" F{}", num(val.0))),
+                }
+                let code = if clockwise { "G2" } else { "G3" };
+                format!("{} {}", code, params)
+            }
             Command::MoveMotorTo {m, l, f} => {
                 match f {
-                    None => format!("G6 M{} L{}", m, l),
-                    Some(val) => format!("G6 M{} L{} F{}", m, l, val),
+                    None => format!("G6 M{} L{}", m, num(l.0)),
+                    Some(val) => format!("G6 M{} L{} F{}", m, num(l.0), num(val.0)),
+                }
+            }
+            Command::MoveToHome { ref axes } => {
+                let mut code = String::from("G28");
+                for axis in axes.iter() {
+                    code.push(' ');
+                    code.push(axis.letter());
                 }
+                code
             }
-            Command::MoveToHome => format!("G28"),
             Command::NoOp => format!(""),
             Command::Pause { s, p } => {
                 let mut params = String::new();
                 match s {
                     None => (),
-                    Some(val) => params.push_str(&format!("S{}", val)),
+                    Some(val) => params.push_str(&format!("S{}", num(val))),
                 }
                 match p {
                     None => (),
-                    Some(val) => params.push_str(&format!("P{}", val)),
+                    Some(val) => params.push_str(&format!("P{}", num(val))),
                 }
                 format!("G4 {}", params)
             }
+            Command::QueryPosition => String::from("M114"),
+            Command::EnableMotors => String::from("M17"),
+            Command::DisableMotors => String::from("M18"),
+            Command::Heartbeat => String::from("M7000"),
             Command::SetAbsolute => String::from("G90"),
             Command::SetAttachPosition { n, x, y, z } => {
                 let mut params = String::new();
-                params.push_str(&format!("X{} ", x));
-                params.push_str(&format!("Y{} ", y));
-                params.push_str(&format!("Z{} ", z));
+                params.push_str(&format!("X{} ", num(x.0)));
+                params.push_str(&format!("Y{} ", num(y.0)));
+                params.push_str(&format!("Z{} ", num(z.0)));
                 let code = match n {
                     1 => "M131",
                     2 => "M132",
                     3 => "M133",
-                    _ => panic!("Unsupported attach point number"),
+                    _ => {
+                        return Err(Error::Encoding {
+                            context: format!("unsupported attach point number {}", n),
+                        })
+                    }
                 };
                 format!("{} {}", code, params)
             }
@@ -123,21 +254,36 @@ impl GCode for Command {
                 let mut params = String::new();
                 match x {
                     None => (),
-                    Some(val) => params.push_str(&format!("X{} ", val)),
+                    Some(val) => params.push_str(&format!("X{} ", num(val.0))),
                 }
                 match y {
                     None => (),
-                    Some(val) => params.push_str(&format!("Y{} ", val)),
+                    Some(val) => params.push_str(&format!("Y{} ", num(val.0))),
                 }
                 match z {
                     None => (),
-                    Some(val) => params.push_str(&format!("Z{} ", val)),
+                    Some(val) => params.push_str(&format!("Z{} ", num(val.0))),
                 }
                 format!("G92 {}", params)
             }
             Command::SetMotorZero {m} => format!("G92 M{}", m),
+            Command::SetFeedOverride { s } => format!("M220 S{}", num(s)),
             Command::SetRelative => String::from("G91"),
             Command::Shutdown => String::from("M00"),
-        }
+        };
+        Ok(code)
+    }
+}
+
+/// Round `value` to `precision` decimals, without the trailing zeros.
+fn number(value: Num, precision: usize) -> String {
+    let mut text = format!("{:.*}", precision, value);
+    if text.contains('.') {
+        let length = text.trim_end_matches('0').trim_end_matches('.').len();
+        text.truncate(length);
+    }
+    if text == "-0" {
+        text = String::from("0");
     }
+    text
 }
@@ -0,0 +1,140 @@
+//! Keep the link to the firmware from being flooded: targets identical to
+//! the last ones sent are dropped, and targets queued while the link was busy
+//! are merged into the latest one.
+
+use driver::command::Command;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Statistics {
+    /// Commands passed on to the firmware.
+    pub sent: usize,
+    /// Targets dropped for being the same as the last one sent.
+    pub suppressed: usize,
+    /// Targets replaced by a later one before being sent.
+    pub coalesced: usize,
+}
+
+/// Part of the machine a target command moves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Slot {
+    Translation,
+    Rotation,
+    Motor(usize),
+}
+
+#[derive(Debug)]
+pub struct Filter {
+    /// Whether the firmware is in relative mode, unknown until told. Only
+    /// absolute targets can be dropped or merged.
+    relative: Option<bool>,
+    /// Last target sent for each slot.
+    last: HashMap<Slot, Command>,
+    statistics: Statistics,
+}
+
+impl Filter {
+    pub fn new() -> Filter {
+        Filter {
+            relative: None,
+            last: HashMap::new(),
+            statistics: Statistics::default(),
+        }
+    }
+
+    pub fn statistics(&self) -> Statistics {
+        self.statistics
+    }
+
+    /// Let the next targets through even if repeated, the firmware having
+    /// moved or halted the machine by itself, e.g. on a fault.
+    pub fn forget(&mut self) {
+        self.last.clear();
+    }
+
+    /// Commands to write out of those received at once, in order. A target
+    /// only replaces an earlier one of the same slot when no other command
+    /// lies between them.
+    pub fn batch(&mut self, commands: Vec<Command>) -> Vec<Command> {
+        let mut pending: Vec<Command> = Vec::new();
+        // Targets cannot be merged across the commands before this index
+        let mut barrier = 0;
+        let mut relative = self.relative;
+        for command in commands {
+            match slot(&command) {
+                Some(target) => {
+                    if relative == Some(false) {
+                        let earlier = pending[barrier..]
+                            .iter()
+                            .position(|other| slot(other) == Some(target) && covers(&command, other));
+                        if let Some(index) = earlier {
+                            pending.remove(barrier + index);
+                            self.statistics.coalesced += 1;
+                        }
+                    }
+                    pending.push(command);
+                }
+                None => {
+                    relative = mode(&command).or(relative);
+                    pending.push(command);
+                    barrier = pending.len();
+                }
+            }
+        }
+        pending.into_iter().filter(|command| self.keep(command)).collect()
+    }
+
+    fn keep(&mut self, command: &Command) -> bool {
+        match slot(command) {
+            Some(target) => {
+                if self.relative == Some(false) && self.last.get(&target) == Some(command) {
+                    self.statistics.suppressed += 1;
+                    return false;
+                }
+                self.last.insert(target, command.clone());
+            }
+            None => {
+                self.relative = mode(command).or(self.relative);
+                match *command {
+                    Command::NoOp | Command::QueryPosition | Command::Heartbeat => (),
+                    // Anything else may move the machine away from the last targets
+                    _ => self.last.clear(),
+                }
+            }
+        }
+        self.statistics.sent += 1;
+        true
+    }
+}
+
+fn slot(command: &Command) -> Option<Slot> {
+    match *command {
+        Command::MoveTo { .. } => Some(Slot::Translation),
+        Command::RotateTo { .. } => Some(Slot::Rotation),
+        Command::MoveMotorTo { m, .. } => Some(Slot::Motor(m)),
+        _ => None,
+    }
+}
+
+fn mode(command: &Command) -> Option<bool> {
+    match *command {
+        Command::SetAbsolute => Some(false),
+        Command::SetRelative => Some(true),
+        _ => None,
+    }
+}
+
+/// Whether `later` sets every coordinate `earlier` does, so that the latter
+/// can be dropped.
+fn covers(later: &Command, earlier: &Command) -> bool {
+    match (later, earlier) {
+        (&Command::MoveTo { x, y, z, .. }, &Command::MoveTo { x: ex, y: ey, z: ez, .. }) => {
+            (x.is_some() || ex.is_none()) && (y.is_some() || ey.is_none()) && (z.is_some() || ez.is_none())
+        }
+        (&Command::RotateTo { u, v, .. }, &Command::RotateTo { u: eu, v: ev, .. }) => {
+            (u.is_some() || eu.is_none()) && (v.is_some() || ev.is_none())
+        }
+        (&Command::MoveMotorTo { .. }, &Command::MoveMotorTo { .. }) => true,
+        _ => false,
+    }
+}
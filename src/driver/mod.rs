@@ -1,47 +1,158 @@
+use config::Transport;
 use driver::command::{Command, GCode};
+use driver::filter::{Filter, Statistics};
+use error::{Context, Result};
+use message::Message;
+use recorder::{Entry, Recorder};
+use sensor::event::Event;
 use std::io::prelude::*;
-use std::net::TcpStream;
-use std::sync::mpsc;
-use std::{thread, time};
+use std::io::BufReader;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use transport;
+use transport::{Role, Stream};
 
 pub mod command;
+pub mod filter;
 
-const WAIT_DURATION_MS: u64 = 1;
+pub struct Link {
+    done: mpsc::Receiver<()>,
+    statistics: Arc<Mutex<Statistics>>,
+}
+
+impl Link {
+    fn new(done: mpsc::Receiver<()>, statistics: Arc<Mutex<Statistics>>) -> Link {
+        Link {
+            done: done,
+            statistics: statistics,
+        }
+    }
+
+    /// Wait for the driver thread to flush the queued commands and close the
+    /// connection. Every `Sender` must have been dropped beforehand, otherwise
+    /// this only returns after `timeout`. Returns whether the queue was drained.
+    pub fn close(&self, timeout: Duration) -> bool {
+        self.done.recv_timeout(timeout).is_ok()
+    }
+
+    pub fn statistics(&self) -> Statistics {
+        *self.statistics.lock().unwrap()
+    }
+}
+
+/// Commands sent to the returned sender are written to the firmware, its
+/// answers and the errors of the link come back on `messages`.
+pub fn connect_driver(
+    transport: &Transport,
+    precision: usize,
+    messages: &mpsc::Sender<Message>,
+    recorder: &Recorder,
+) -> Result<(mpsc::Sender<Command>, Link)> {
+    let stream = transport::connect(transport, Role::Driver)?;
+    let reader = stream.try_clone()?;
 
-pub fn connect_driver(address: &str) -> mpsc::Sender<Command> {
     let (tx, rx) = mpsc::channel();
+    let (done_tx, done_rx) = mpsc::channel();
+    let events = messages.clone();
+    let errors = messages.clone();
+    let recorder = recorder.clone();
+    let statistics = Arc::new(Mutex::new(Statistics::default()));
+    let shared = statistics.clone();
+    let moved = Arc::new(AtomicBool::new(false));
+    let reported = moved.clone();
 
-    if let Some(stream) = TcpStream::connect(address).ok() {
-        thread::spawn(move || emit(stream, rx));
-    } else {
-        thread::spawn(move || dummy(rx));
-    };
+    thread::spawn(move || {
+        if let Err(error) = emit(stream, rx, precision, &errors, &recorder, &shared, &moved) {
+            errors.send(Message::Error(error)).ok();
+        }
+        done_tx.send(()).ok();
+    });
+    // Ends with the connection, once `emit` has shut it down
+    thread::spawn(move || listen(reader, &events, &reported));
 
-    return tx;
+    return Ok((tx, Link::new(done_rx, statistics)));
 }
 
-fn emit(mut stream: TcpStream, rx: mpsc::Receiver<Command>) {
-    let wait_duration = time::Duration::from_millis(WAIT_DURATION_MS);
-    stream.set_nodelay(true).unwrap();
-    loop {
-        for received in rx.try_iter() {
-            let code = received.to_gcode();
-            if code.len() > 0 {
-                stream.write(format!("{}\n", code).as_bytes()).unwrap();
-                stream.flush().unwrap();
-            }
+fn emit(
+    mut stream: Box<Stream>,
+    rx: mpsc::Receiver<Command>,
+    precision: usize,
+    errors: &mpsc::Sender<Message>,
+    recorder: &Recorder,
+    statistics: &Mutex<Statistics>,
+    moved: &AtomicBool,
+) -> Result<()> {
+    let mut filter = Filter::new();
+    for received in rx.iter() {
+        if moved.swap(false, Ordering::SeqCst) {
+            filter.forget();
+        }
+        let commands = filter.batch(backlog(received, &rx, recorder));
+        *statistics.lock().unwrap() = filter.statistics();
+        for command in commands {
+            write(&mut stream, &command, precision, errors)?;
         }
-        thread::sleep(wait_duration);
     }
+    stream.shutdown();
+    Ok(())
+}
+
+/// The received command and whatever queued up behind it while the link was
+/// busy.
+fn backlog(received: Command, rx: &mpsc::Receiver<Command>, recorder: &Recorder) -> Vec<Command> {
+    let mut commands = vec![received];
+    commands.extend(rx.try_iter());
+    for command in commands.iter() {
+        recorder.record(Entry::Command(command.clone()));
+    }
+    commands
 }
 
-fn dummy(rx: mpsc::Receiver<Command>) {
-    let wait_duration = time::Duration::from_millis(WAIT_DURATION_MS);
-    loop {
-        for received in rx.try_iter() {
-            if received == Command::NoOp { continue }
-            println!("{:?}\r", received);
+fn write(
+    stream: &mut Write,
+    command: &Command,
+    precision: usize,
+    errors: &mpsc::Sender<Message>,
+) -> Result<()> {
+    let code = match command.to_gcode(precision) {
+        Ok(code) => code,
+        Err(error) => {
+            // Skip the faulty command, the next ones may still be fine
+            errors.send(Message::Error(error)).ok();
+            return Ok(());
+        }
+    };
+    if code.len() > 0 {
+        stream
+            .write_all(format!("{}\n", code).as_bytes())
+            .context("writing to driver")?;
+        stream.flush().context("writing to driver")?;
+    }
+    Ok(())
+}
+
+/// Forward the answers of the firmware, e.g. to `M114`, as sensor events.
+/// `moved` is set when the firmware reports a position or a fault, which
+/// may not match the last targets sent.
+fn listen(stream: Box<Stream>, tx: &mpsc::Sender<Message>, moved: &AtomicBool) {
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let event = Event::parse(&line);
+        let report = match event {
+            Event::Position { .. } | Event::Homed { .. } => true,
+            Event::Content { ref string } => string.starts_with("error"),
+            Event::Tension { .. } => false,
+        };
+        if report {
+            moved.store(true, Ordering::SeqCst);
+        }
+        if tx.send(Message::Event(event)).is_err() {
+            break;
         }
-        thread::sleep(wait_duration);
     }
 }
@@ -0,0 +1,78 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+use std::sync::mpsc;
+
+#[derive(Debug)]
+pub enum Error {
+    /// An I/O operation on one of the links failed.
+    Io { context: String, source: io::Error },
+    /// No gamepad support could be initialized.
+    Gamepad { context: String },
+    /// The other end of a channel is gone, usually because its thread died.
+    Disconnected { context: String },
+    /// A command cannot be expressed as G-code.
+    Encoding { context: String },
+    /// Some input, like a recorded session, cannot be understood.
+    Decoding { context: String },
+    /// The machine is not in a state allowing the operation, e.g. not homed.
+    NotReady { context: String },
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io {
+                ref context,
+                ref source,
+            } => write!(f, "{}: {}", context, source),
+            Error::Gamepad { ref context } => write!(f, "gamepad unavailable: {}", context),
+            Error::Disconnected { ref context } => write!(f, "{} disconnected", context),
+            Error::Encoding { ref context } => write!(f, "cannot encode command: {}", context),
+            Error::Decoding { ref context } => write!(f, "cannot decode {}", context),
+            Error::NotReady { ref context } => write!(f, "not ready: {}", context),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(error::Error + 'static)> {
+        match *self {
+            Error::Io { ref source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl<T> From<mpsc::SendError<T>> for Error {
+    fn from(_: mpsc::SendError<T>) -> Error {
+        Error::Disconnected {
+            context: String::from("channel"),
+        }
+    }
+}
+
+/// Attach a description of what was being done to a lower level error.
+pub trait Context<T> {
+    fn context(self, context: &str) -> Result<T>;
+}
+
+impl<T> Context<T> for io::Result<T> {
+    fn context(self, context: &str) -> Result<T> {
+        self.map_err(|source| Error::Io {
+            context: String::from(context),
+            source: source,
+        })
+    }
+}
+
+impl<T, U> Context<T> for result::Result<T, mpsc::SendError<U>> {
+    fn context(self, context: &str) -> Result<T> {
+        self.map_err(|_| Error::Disconnected {
+            context: String::from(context),
+        })
+    }
+}
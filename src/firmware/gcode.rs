@@ -0,0 +1,136 @@
+use driver::command::{Axis, Command, Num};
+use error::{Error, Result};
+use units::{Degrees, DegreesPerMinute, Millimeters, MillimetersPerMinute};
+
+/// Parse a line produced by `GCode::to_gcode` back into a `Command`.
+pub fn parse(line: &str) -> Result<Command> {
+    let words = split(line)?;
+    let (letter, number) = match words.first() {
+        Some(&(letter, ref number)) => (letter, number.clone()),
+        None => return Ok(Command::NoOp),
+    };
+    let params = &words[1..];
+    let code = format!("{}{}", letter, number);
+
+    let command = match code.as_str() {
+        "G0" | "G1" => {
+            let f = optional(params, 'F', line)?;
+            if has(params, 'U') || has(params, 'V') {
+                Command::RotateTo {
+                    u: optional(params, 'U', line)?.map(Degrees),
+                    v: optional(params, 'V', line)?.map(Degrees),
+                    f: f.map(DegreesPerMinute),
+                }
+            } else {
+                Command::MoveTo {
+                    x: optional(params, 'X', line)?.map(Millimeters),
+                    y: optional(params, 'Y', line)?.map(Millimeters),
+                    z: optional(params, 'Z', line)?.map(Millimeters),
+                    f: f.map(MillimetersPerMinute),
+                }
+            }
+        }
+        "G2" | "G3" => Command::ArcTo {
+            clockwise: code == "G2",
+            x: optional(params, 'X', line)?.map(Millimeters),
+            y: optional(params, 'Y', line)?.map(Millimeters),
+            z: optional(params, 'Z', line)?.map(Millimeters),
+            i: Millimeters(required(params, 'I', line)?),
+            j: Millimeters(required(params, 'J', line)?),
+            f: optional(params, 'F', line)?.map(MillimetersPerMinute),
+        },
+        "G4" => Command::Pause {
+            p: optional(params, 'P', line)?,
+            s: optional(params, 'S', line)?,
+        },
+        "G6" => Command::MoveMotorTo {
+            m: index(params, 'M', line)?,
+            l: Millimeters(required(params, 'L', line)?),
+            f: optional(params, 'F', line)?.map(MillimetersPerMinute),
+        },
+        "G28" => Command::MoveToHome {
+            axes: params
+                .iter()
+                .map(|&(letter, _)| Axis::from_letter(letter).ok_or_else(|| invalid(line)))
+                .collect::<Result<Vec<Axis>>>()?,
+        },
+        "G90" => Command::SetAbsolute,
+        "G91" => Command::SetRelative,
+        "G92" => {
+            if has(params, 'M') {
+                Command::SetMotorZero {
+                    m: index(params, 'M', line)?,
+                }
+            } else {
+                Command::SetPosition {
+                    x: optional(params, 'X', line)?.map(Millimeters),
+                    y: optional(params, 'Y', line)?.map(Millimeters),
+                    z: optional(params, 'Z', line)?.map(Millimeters),
+                }
+            }
+        }
+        "M131" | "M132" | "M133" => Command::SetAttachPosition {
+            n: number[2..].parse().unwrap_or(0),
+            x: Millimeters(required(params, 'X', line)?),
+            y: Millimeters(required(params, 'Y', line)?),
+            z: Millimeters(required(params, 'Z', line)?),
+        },
+        "M00" | "M0" => Command::Shutdown,
+        "M17" => Command::EnableMotors,
+        "M18" => Command::DisableMotors,
+        "M114" => Command::QueryPosition,
+        "M7000" => Command::Heartbeat,
+        "M220" => Command::SetFeedOverride {
+            s: required(params, 'S', line)?,
+        },
+        _ => return Err(invalid(line)),
+    };
+    Ok(command)
+}
+
+/// Split a line into its words, a letter followed by a number. Words are not
+/// necessarily separated by spaces, e.g. `G4 S1P500`.
+fn split(line: &str) -> Result<Vec<(char, String)>> {
+    let mut words: Vec<(char, String)> = Vec::new();
+    for c in line.trim().chars() {
+        if c.is_ascii_alphabetic() {
+            words.push((c.to_ascii_uppercase(), String::new()));
+        } else if c.is_whitespace() {
+            continue;
+        } else {
+            match words.last_mut() {
+                Some(word) => word.1.push(c),
+                None => return Err(invalid(line)),
+            }
+        }
+    }
+    Ok(words)
+}
+
+fn has(params: &[(char, String)], letter: char) -> bool {
+    params.iter().any(|&(l, _)| l == letter)
+}
+
+fn optional(params: &[(char, String)], letter: char, line: &str) -> Result<Option<Num>> {
+    match params.iter().find(|&&(l, _)| l == letter) {
+        Some(&(_, ref value)) => value.parse().map(Some).map_err(|_| invalid(line)),
+        None => Ok(None),
+    }
+}
+
+fn required(params: &[(char, String)], letter: char, line: &str) -> Result<Num> {
+    optional(params, letter, line)?.ok_or_else(|| invalid(line))
+}
+
+fn index(params: &[(char, String)], letter: char, line: &str) -> Result<usize> {
+    match params.iter().find(|&&(l, _)| l == letter) {
+        Some(&(_, ref value)) => value.parse().map_err(|_| invalid(line)),
+        None => Err(invalid(line)),
+    }
+}
+
+fn invalid(line: &str) -> Error {
+    Error::Decoding {
+        context: format!("G-code `{}`", line.trim()),
+    }
+}
@@ -0,0 +1,290 @@
+use driver::command::{Axis, Command, AXES};
+use units::Quantity;
+
+pub const NUM_MOTORS: usize = 4;
+
+// Limits of the simulated hardware
+const TRANSLATION_ACCELERATION: f32 = 500.0; // mm/s²
+const ROTATION_ACCELERATION: f32 = 200.0; // deg/s²
+const MOTOR_ACCELERATION: f32 = 500.0; // mm/s²
+const RAPID_FEED: f32 = 60_000.0; // mm/min or deg/min
+const HOST_TIMEOUT: f32 = 2.0; // s without heartbeat before halting
+
+/// A single simulated degree of freedom, moving towards its target with a
+/// trapezoidal speed profile.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Joint {
+    pub position: f32,
+    pub velocity: f32,
+    pub target: f32,
+    /// Maximum speed, per minute as in G-code.
+    pub feed: f32,
+}
+
+impl Joint {
+    fn new(position: f32) -> Joint {
+        Joint {
+            position: position,
+            velocity: 0.,
+            target: position,
+            feed: RAPID_FEED,
+        }
+    }
+
+    fn move_to(&mut self, target: f32, feed: f32) {
+        self.target = target;
+        self.feed = feed;
+    }
+
+    fn set(&mut self, position: f32) {
+        self.position = position;
+        self.target = position;
+        self.velocity = 0.;
+    }
+
+    fn halt(&mut self) {
+        let position = self.position;
+        self.set(position);
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.position != self.target || self.velocity != 0.
+    }
+
+    fn step(&mut self, dt: f32, acceleration: f32) {
+        let distance = self.target - self.position;
+        if distance == 0. && self.velocity == 0. {
+            return;
+        }
+        // Fastest speed from which the joint can still stop on the target
+        let stopping = (2. * acceleration * distance.abs()).sqrt();
+        let desired = distance.signum() * stopping.min(self.feed / 60.);
+        let change = (desired - self.velocity)
+            .max(-acceleration * dt)
+            .min(acceleration * dt);
+        self.velocity += change;
+        self.position += self.velocity * dt;
+
+        let remaining = self.target - self.position;
+        let overshot = remaining != 0. && remaining.signum() != distance.signum();
+        let arrived = remaining.abs() < 1e-3 && self.velocity.abs() <= acceleration * dt;
+        if overshot || arrived {
+            let target = self.target;
+            self.set(target);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Machine {
+    pub x: Joint,
+    pub y: Joint,
+    pub z: Joint,
+    pub u: Joint,
+    pub v: Joint,
+    pub motors: [Joint; NUM_MOTORS],
+    pub attach: [[f32; 3]; 3],
+    pub relative: bool,
+    /// Feed rate override, in percent.
+    pub feed_override: f32,
+    /// Disabled motors ignore the motion commands.
+    pub enabled: bool,
+    /// Seconds since the last host heartbeat, none before the first one.
+    pub quiet: Option<f32>,
+    /// A blocking home is running, the host heartbeats queuing up unread.
+    pub homing: bool,
+    /// Every command received, in order.
+    pub received: Vec<Command>,
+}
+
+impl Machine {
+    pub fn new() -> Machine {
+        Machine {
+            x: Joint::new(0.),
+            y: Joint::new(0.),
+            z: Joint::new(0.),
+            u: Joint::new(0.),
+            v: Joint::new(0.),
+            motors: [Joint::new(0.); NUM_MOTORS],
+            attach: [[0.; 3]; 3],
+            relative: false,
+            feed_override: 100.,
+            enabled: true,
+            quiet: None,
+            homing: false,
+            received: Vec::new(),
+        }
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.joints().iter().any(|joint| joint.is_moving())
+            || self.motors.iter().any(|joint| joint.is_moving())
+    }
+
+    pub fn execute(&mut self, command: &Command) {
+        self.received.push(command.clone());
+        if !self.enabled && command.is_motion() {
+            return;
+        }
+        let scale = self.feed_override / 100.;
+        match *command {
+            Command::MoveTo { x, y, z, f } => {
+                let feed = feed(f) * scale;
+                let relative = self.relative;
+                move_joint(&mut self.x, x, feed, relative);
+                move_joint(&mut self.y, y, feed, relative);
+                move_joint(&mut self.z, z, feed, relative);
+            }
+            Command::RotateTo { u, v, f } => {
+                let feed = feed(f) * scale;
+                let relative = self.relative;
+                move_joint(&mut self.u, u, feed, relative);
+                move_joint(&mut self.v, v, feed, relative);
+            }
+            Command::MoveMotorTo { m, l, f } => {
+                let relative = self.relative;
+                if let Some(motor) = self.motors.get_mut(m) {
+                    move_joint(motor, Some(l), feed(f) * scale, relative);
+                }
+            }
+            // Arcs are simulated along their chord, the end point is what matters here
+            Command::ArcTo { x, y, z, f, .. } => {
+                let feed = feed(f) * scale;
+                let relative = self.relative;
+                move_joint(&mut self.x, x, feed, relative);
+                move_joint(&mut self.y, y, feed, relative);
+                move_joint(&mut self.z, z, feed, relative);
+            }
+            Command::MoveToHome { ref axes } => {
+                let axes = if axes.is_empty() { &AXES[..] } else { &axes[..] };
+                for &axis in axes.iter() {
+                    self.joint_mut(axis).move_to(0., RAPID_FEED * scale);
+                }
+            }
+            Command::EnableMotors => self.enabled = true,
+            Command::DisableMotors => {
+                self.halt();
+                self.enabled = false;
+            }
+            Command::SetFeedOverride { s } => self.feed_override = s,
+            Command::SetAbsolute => self.relative = false,
+            Command::SetRelative => self.relative = true,
+            Command::SetAttachPosition { n, x, y, z } => {
+                if let Some(attach) = self.attach.get_mut(n.wrapping_sub(1)) {
+                    *attach = [x.0, y.0, z.0];
+                }
+            }
+            Command::SetPosition { x, y, z } => {
+                set_joint(&mut self.x, x);
+                set_joint(&mut self.y, y);
+                set_joint(&mut self.z, z);
+            }
+            Command::SetMotorZero { m } => {
+                if let Some(motor) = self.motors.get_mut(m) {
+                    motor.set(0.);
+                }
+            }
+            Command::Shutdown => self.halt(),
+            Command::Heartbeat => self.quiet = Some(0.),
+            Command::NoOp | Command::Pause { .. } | Command::QueryPosition => (),
+        }
+    }
+
+    pub fn step(&mut self, dt: f32) {
+        self.x.step(dt, TRANSLATION_ACCELERATION);
+        self.y.step(dt, TRANSLATION_ACCELERATION);
+        self.z.step(dt, TRANSLATION_ACCELERATION);
+        self.u.step(dt, ROTATION_ACCELERATION);
+        self.v.step(dt, ROTATION_ACCELERATION);
+        for motor in self.motors.iter_mut() {
+            motor.step(dt, MOTOR_ACCELERATION);
+        }
+
+        // A silent host may have hung with the machine moving, unless it
+        // cannot be heard until the home is over
+        if let Some(quiet) = self.quiet {
+            if self.homing {
+                self.quiet = Some(0.);
+            } else if quiet + dt > HOST_TIMEOUT {
+                self.halt();
+                self.quiet = None;
+            } else {
+                self.quiet = Some(quiet + dt);
+            }
+        }
+    }
+
+    /// Line streamed on the sensor link.
+    pub fn status(&self) -> String {
+        let mut status = format!(
+            "X:{:.3} Y:{:.3} Z:{:.3} U:{:.3} V:{:.3}",
+            self.x.position, self.y.position, self.z.position, self.u.position, self.v.position
+        );
+        for (m, motor) in self.motors.iter().enumerate() {
+            status.push_str(&format!(" M{}:{:.3}", m, motor.position));
+        }
+        status
+    }
+
+    fn halt(&mut self) {
+        for joint in self.joints_mut().iter_mut() {
+            joint.halt();
+        }
+        for motor in self.motors.iter_mut() {
+            motor.halt();
+        }
+    }
+
+    /// Whether the axes are at rest at home, rather than halted on the way.
+    pub fn is_home(&self, axes: &[Axis]) -> bool {
+        axes.iter().all(|&axis| {
+            let joint = self.joint(axis);
+            joint.position == 0. && !joint.is_moving()
+        })
+    }
+
+    fn joint(&self, axis: Axis) -> &Joint {
+        match axis {
+            Axis::X => &self.x,
+            Axis::Y => &self.y,
+            Axis::Z => &self.z,
+            Axis::U => &self.u,
+            Axis::V => &self.v,
+        }
+    }
+
+    fn joint_mut(&mut self, axis: Axis) -> &mut Joint {
+        match axis {
+            Axis::X => &mut self.x,
+            Axis::Y => &mut self.y,
+            Axis::Z => &mut self.z,
+            Axis::U => &mut self.u,
+            Axis::V => &mut self.v,
+        }
+    }
+
+    fn joints(&self) -> [&Joint; 5] {
+        [&self.x, &self.y, &self.z, &self.u, &self.v]
+    }
+
+    fn joints_mut(&mut self) -> [&mut Joint; 5] {
+        [&mut self.x, &mut self.y, &mut self.z, &mut self.u, &mut self.v]
+    }
+}
+
+fn feed<Q: Quantity>(f: Option<Q>) -> f32 {
+    f.map(Q::value).unwrap_or(RAPID_FEED)
+}
+
+fn move_joint<Q: Quantity>(joint: &mut Joint, value: Option<Q>, feed: f32, relative: bool) {
+    if let Some(value) = value {
+        let base = if relative { joint.target } else { 0. };
+        joint.move_to(base + value.value(), feed);
+    }
+}
+
+fn set_joint<Q: Quantity>(joint: &mut Joint, value: Option<Q>) {
+    if let Some(value) = value {
+        joint.set(value.value());
+    }
+}
@@ -0,0 +1,149 @@
+//! Simulated firmware speaking the same protocol as the real one, to run the
+//! whole program without hardware.
+
+use clock::seconds;
+use error::{Context, Result};
+use driver::command::{Axis, Command, AXES};
+use firmware::machine::Machine;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub mod gcode;
+pub mod machine;
+
+const SIMULATION_PERIOD_MS: u64 = 5;
+const STATUS_PERIOD_MS: u64 = 100;
+
+pub struct Firmware {
+    driver: TcpListener,
+    sensor: TcpListener,
+    machine: Arc<Mutex<Machine>>,
+}
+
+impl Firmware {
+    /// Listen for the driver and the sensor links on the given addresses.
+    /// Port 0 picks a free port, see `driver_address` and `sensor_address`.
+    pub fn bind(driver: &str, sensor: &str) -> Result<Firmware> {
+        Ok(Firmware {
+            driver: TcpListener::bind(driver).context("binding driver port")?,
+            sensor: TcpListener::bind(sensor).context("binding sensor port")?,
+            machine: Arc::new(Mutex::new(Machine::new())),
+        })
+    }
+
+    pub fn driver_address(&self) -> Result<SocketAddr> {
+        self.driver.local_addr().context("reading driver address")
+    }
+
+    pub fn sensor_address(&self) -> Result<SocketAddr> {
+        self.sensor.local_addr().context("reading sensor address")
+    }
+
+    /// Start serving in background threads. The returned machine can be
+    /// inspected while the simulation runs.
+    pub fn start(self) -> Arc<Mutex<Machine>> {
+        let machine = self.machine.clone();
+        thread::spawn(move || simulate(machine));
+
+        let machine = self.machine.clone();
+        let driver = self.driver;
+        thread::spawn(move || {
+            for stream in driver.incoming().filter_map(|stream| stream.ok()) {
+                receive(stream, &machine);
+            }
+        });
+
+        let machine = self.machine.clone();
+        let sensor = self.sensor;
+        thread::spawn(move || {
+            for stream in sensor.incoming().filter_map(|stream| stream.ok()) {
+                report(stream, &machine);
+            }
+        });
+
+        return self.machine;
+    }
+}
+
+fn simulate(machine: Arc<Mutex<Machine>>) {
+    let period = Duration::from_millis(SIMULATION_PERIOD_MS);
+    let mut last = Instant::now();
+    loop {
+        thread::sleep(period);
+        let now = Instant::now();
+        let dt = seconds(now - last) as f32;
+        last = now;
+        machine.lock().unwrap().step(dt);
+    }
+}
+
+/// Execute the G-code sent by one driver connection, until it closes.
+/// Queries are answered on the same connection.
+fn receive(stream: TcpStream, machine: &Arc<Mutex<Machine>>) {
+    let mut reply = match stream.try_clone() {
+        Ok(reply) => reply,
+        Err(_) => return,
+    };
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let command = match gcode::parse(&line) {
+            Ok(command) => command,
+            Err(error) => {
+                println!("{}", error);
+                continue;
+            }
+        };
+        machine.lock().unwrap().execute(&command);
+        match command {
+            Command::QueryPosition => {
+                writeln!(reply, "{}", machine.lock().unwrap().status()).ok();
+            }
+            // As on the real firmware, nothing else runs until the axes are home
+            Command::MoveToHome { ref axes } => {
+                let axes = if axes.is_empty() { &AXES[..] } else { &axes[..] };
+                let letters: Vec<String> = axes.iter().map(|axis| axis.letter().to_string()).collect();
+                if home(machine, axes) {
+                    writeln!(reply, "homed {}", letters.join(" ")).ok();
+                } else {
+                    writeln!(reply, "error: homing of {} halted", letters.join(" ")).ok();
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Wait for the axes to reach home, without halting on the host heartbeats
+/// that cannot be read meanwhile. Returns whether they got there.
+fn home(machine: &Arc<Mutex<Machine>>, axes: &[Axis]) -> bool {
+    machine.lock().unwrap().homing = true;
+    wait_until_idle(machine);
+    let mut machine = machine.lock().unwrap();
+    machine.homing = false;
+    machine.is_home(axes)
+}
+
+fn wait_until_idle(machine: &Arc<Mutex<Machine>>) {
+    let period = Duration::from_millis(SIMULATION_PERIOD_MS);
+    while machine.lock().unwrap().is_moving() {
+        thread::sleep(period);
+    }
+}
+
+/// Stream the machine status to one sensor connection, until it closes.
+fn report(mut stream: TcpStream, machine: &Arc<Mutex<Machine>>) {
+    let period = Duration::from_millis(STATUS_PERIOD_MS);
+    loop {
+        let status = machine.lock().unwrap().status();
+        if writeln!(stream, "{}", status).is_err() {
+            break;
+        }
+        thread::sleep(period);
+    }
+}
@@ -0,0 +1,118 @@
+//! Coordinate frames. A vector carries its frame in its type, so that
+//! platform relative inputs cannot be sent to the firmware as they are.
+
+use na::{Rotation3, Vector3};
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{Add, AddAssign, Mul, Sub};
+use units::{Degrees, Quantity};
+
+pub trait Frame: fmt::Debug + Clone + Copy + PartialEq {}
+
+/// Frame of the room, in which the cable attach points are measured.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct World;
+
+/// Coordinates of the firmware, the ones written in the G-code.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Machine;
+
+/// Frame moving and rotating with the platform.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Platform;
+
+impl Frame for World {}
+impl Frame for Machine {}
+impl Frame for Platform {}
+
+/// Tilt of the platform, around the X axis for `u` and the Y axis for `v`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Attitude {
+    pub u: Degrees,
+    pub v: Degrees,
+}
+
+impl Attitude {
+    /// Rotation from the platform to the world frame: `u` around X, then
+    /// `v` around Y.
+    fn rotation(&self) -> Rotation3<f32> {
+        Rotation3::from_euler_angles(self.u.0.to_radians(), self.v.0.to_radians(), 0.)
+    }
+}
+
+/// Three components of a quantity `U`, in frame `F`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vector<F: Frame, U> {
+    pub x: U,
+    pub y: U,
+    pub z: U,
+    frame: PhantomData<F>,
+}
+
+impl<F: Frame, U> Vector<F, U> {
+    pub fn new(x: U, y: U, z: U) -> Vector<F, U> {
+        Vector {
+            x: x,
+            y: y,
+            z: z,
+            frame: PhantomData,
+        }
+    }
+
+    /// Apply `f` to each component.
+    pub fn map<V, T: Fn(U) -> V>(self, f: T) -> Vector<F, V> {
+        Vector::new(f(self.x), f(self.y), f(self.z))
+    }
+}
+
+impl<U> Vector<World, U> {
+    /// The firmware is configured with the attach points measured in the
+    /// room, so both frames share their origin and axes.
+    pub fn to_machine(self) -> Vector<Machine, U> {
+        Vector::new(self.x, self.y, self.z)
+    }
+}
+
+impl<U> Vector<Machine, U> {
+    pub fn to_world(self) -> Vector<World, U> {
+        Vector::new(self.x, self.y, self.z)
+    }
+}
+
+impl<U: Quantity> Vector<Platform, U> {
+    /// Express in the world frame, the platform being tilted by `attitude`.
+    pub fn to_world(self, attitude: &Attitude) -> Vector<World, U> {
+        let vector = Vector3::new(self.x.value(), self.y.value(), self.z.value());
+        let rotated = attitude.rotation() * vector;
+        Vector::new(U::from_value(rotated.x), U::from_value(rotated.y), U::from_value(rotated.z))
+    }
+}
+
+impl<F: Frame, U: Add<Output = U>> Add for Vector<F, U> {
+    type Output = Vector<F, U>;
+    fn add(self, other: Vector<F, U>) -> Vector<F, U> {
+        Vector::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl<F: Frame, U: AddAssign> AddAssign for Vector<F, U> {
+    fn add_assign(&mut self, other: Vector<F, U>) {
+        self.x += other.x;
+        self.y += other.y;
+        self.z += other.z;
+    }
+}
+
+impl<F: Frame, U: Sub<Output = U>> Sub for Vector<F, U> {
+    type Output = Vector<F, U>;
+    fn sub(self, other: Vector<F, U>) -> Vector<F, U> {
+        Vector::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl<F: Frame, U: Mul<f32, Output = U>> Mul<f32> for Vector<F, U> {
+    type Output = Vector<F, U>;
+    fn mul(self, factor: f32) -> Vector<F, U> {
+        Vector::new(self.x * factor, self.y * factor, self.z * factor)
+    }
+}
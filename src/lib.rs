@@ -0,0 +1,31 @@
+extern crate gilrs;
+extern crate libc;
+extern crate nalgebra as na;
+extern crate ncurses;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate signal_hook;
+extern crate toml;
+
+pub mod clock;
+pub mod config;
+pub mod controller;
+pub mod driver;
+pub mod error;
+pub mod firmware;
+pub mod frame;
+pub mod message;
+pub mod mode;
+pub mod physics;
+pub mod planner;
+pub mod recorder;
+pub mod replay;
+pub mod sensor;
+pub mod signal;
+pub mod simulation;
+pub mod state;
+pub mod timer;
+pub mod transport;
+pub mod units;
@@ -1,34 +1,211 @@
-extern crate gilrs;
-extern crate nalgebra as na;
+extern crate fssp_simulator;
 extern crate ncurses;
 
-mod controller;
-mod driver;
-mod mode;
-mod physics;
-mod sensor;
-mod simulation;
+use fssp_simulator::clock::RealClock;
+use fssp_simulator::config::{Config, Transport};
+use fssp_simulator::controller::{connect_controller, connect_keyboard, take_terminal, TERMINAL};
+use fssp_simulator::driver::command::Command;
+use fssp_simulator::driver::connect_driver;
+use fssp_simulator::error::Result;
+use fssp_simulator::mode;
+use fssp_simulator::mode::{master_loop, Context};
+use fssp_simulator::recorder::{connect_recorder, Recorder};
+use fssp_simulator::replay::replay;
+use fssp_simulator::sensor::connect_sensor;
+use fssp_simulator::signal::connect_signals;
+use fssp_simulator::state::{Guard, State, Tracker};
+use fssp_simulator::timer::connect_timer;
+use std::env;
+use std::panic;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
 
-use controller::connect_controller;
-use driver::connect_driver;
-use mode::master_loop;
-use sensor::connect_sensor;
+const CONNECT_ATTEMPTS: u32 = 3;
+const CONNECT_RETRY_MS: u64 = 500;
+const DRAIN_TIMEOUT_MS: u64 = 2_000;
 
 fn main() {
-    let controller = connect_controller();
-    let driver = connect_driver("localhost:16000");
-    let sensor = connect_sensor("localhost:16001");
+    let args: Vec<String> = env::args().collect();
+    let loaded = option(&args, "--config").map(|path| {
+        Config::load(&path).unwrap_or_else(|error| {
+            println!(":: {}", error);
+            std::process::exit(2);
+        })
+    });
+    if let Some(path) = option(&args, "--replay") {
+        let speed = option(&args, "--speed")
+            .and_then(|speed| speed.parse().ok())
+            .unwrap_or(1.);
+        run_replay(&path, speed, loaded.as_ref());
+    }
+    let mut config = loaded.unwrap_or_default();
 
+    let (recorder, recorder_link) = match option(&args, "--record") {
+        Some(path) => match connect_recorder(&path) {
+            Ok((recorder, link)) => (recorder, Some(link)),
+            Err(error) => {
+                println!(":: {}, session will not be recorded", error);
+                (Recorder::disabled(), None)
+            }
+        },
+        None => (Recorder::disabled(), None),
+    };
+
+    let (tx, rx) = mpsc::channel();
+    let precision = config.driver.precision;
+    let transport = config.driver.transport.clone();
+    let mut standard = transport == Transport::Stdout;
+    let (driver, driver_link) = match retry(|| connect_driver(&transport, precision, &tx, &recorder)) {
+        Ok(connection) => connection,
+        Err(error) => {
+            println!(":: {}, commands will only be printed", error);
+            // Nobody is listening for them
+            config.heartbeat.period = 0.;
+            standard = true;
+            connect_driver(&Transport::Stdout, precision, &tx, &recorder).unwrap_or_else(|error| {
+                println!(":: {}", error);
+                std::process::exit(1);
+            })
+        }
+    };
+    let transport = config.sensor.transport.clone();
+    standard = standard || transport == Transport::Stdin;
+    let sensor_link = match retry(|| connect_sensor(&transport, &tx)) {
+        Ok(link) => Some(link),
+        Err(error) => {
+            println!(":: {}, running without sensors", error);
+            // Only the sensor link streams continuously
+            config.heartbeat.timeout = 0.;
+            None
+        }
+    };
+    let timer_link = connect_timer(mode::FREQUENCY, RealClock::new(), &tx);
+    if let Err(error) = connect_signals(&tx) {
+        println!(":: {}, use the quit trigger to stop", error);
+    }
+
+    // The links keep the standard streams, the operator gets the terminal
+    if standard {
+        if let Err(error) = take_terminal(TERMINAL) {
+            println!(":: {}, sharing the standard streams with the links", error);
+        }
+    }
     init_ncurses();
-    master_loop(controller, driver, sensor);
+    let controller_link = match connect_controller(&tx) {
+        Ok(link) => link,
+        Err(error) => {
+            println!(":: {}, keyboard only\r", error);
+            connect_keyboard(&tx)
+        }
+    };
+
+    // Whatever happens in the master loop, the machine must be left halted
+    let halt = driver.clone();
+    let machine = Tracker::new(State::Unhomed, !config.homing.required);
+    let context = Context {
+        driver: Guard::new(driver, &machine),
+        config: config,
+        machine: machine,
+    };
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| master_loop(rx, context, recorder)));
+
+    controller_link.close();
+    timer_link.close();
+    halt.send(Command::Shutdown).ok();
+    drop(halt);
+    if !driver_link.close(Duration::from_millis(DRAIN_TIMEOUT_MS)) {
+        println!(":: Driver queue not drained, some commands were lost\r");
+    }
+    let statistics = driver_link.statistics();
+    println!(
+        ":: Driver: {} commands sent, {} duplicates suppressed, {} coalesced\r",
+        statistics.sent, statistics.suppressed, statistics.coalesced
+    );
+    if let Some(link) = sensor_link {
+        link.close();
+    }
+    if let Some(link) = recorder_link {
+        link.close(Duration::from_millis(DRAIN_TIMEOUT_MS));
+    }
     close_ncurses();
+
+    match result {
+        Ok(Ok(())) => (),
+        Ok(Err(error)) => {
+            println!(":: Stopped: {}", error);
+            std::process::exit(1);
+        }
+        Err(panic) => panic::resume_unwind(panic),
+    }
+}
+
+/// Replay a recorded session without touching the hardware and exit with a
+/// non-zero status if the commands diverge from the recorded ones.
+fn run_replay(path: &str, speed: f64, config: Option<&Config>) -> ! {
+    match replay(path, speed, config) {
+        Ok(ref divergences) if divergences.is_empty() => {
+            println!(":: Replay matches the recorded commands");
+            std::process::exit(0);
+        }
+        Ok(divergences) => {
+            for divergence in divergences.iter() {
+                println!(
+                    ":: Command #{}: expected {:?}, got {:?}",
+                    divergence.index, divergence.expected, divergence.actual
+                );
+            }
+            println!(":: Replay diverged on {} commands", divergences.len());
+            std::process::exit(1);
+        }
+        Err(error) => {
+            println!(":: Replay failed: {}", error);
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Value following `name` on the command line, if any.
+fn option(args: &[String], name: &str) -> Option<String> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|index| args.get(index + 1))
+        .cloned()
+}
+
+/// Try to establish a connection a few times before giving up on it.
+fn retry<T, F>(mut connect: F) -> Result<T>
+where
+    F: FnMut() -> Result<T>,
+{
+    let mut attempt = 1;
+    loop {
+        match connect() {
+            Ok(connection) => return Ok(connection),
+            Err(error) => {
+                if attempt >= CONNECT_ATTEMPTS {
+                    return Err(error);
+                }
+                println!(":: {}, retrying", error);
+                thread::sleep(Duration::from_millis(CONNECT_RETRY_MS * attempt as u64));
+                attempt += 1;
+            }
+        }
+    }
 }
 
 fn init_ncurses() {
     let windows = ncurses::initscr();
-    ncurses::nodelay(windows, true);
+    ncurses::nodelay(windows, false);
     ncurses::noecho();
     ncurses::refresh();
+
+    // Give the terminal back before the panic message gets printed
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        ncurses::endwin();
+        default_hook(info);
+    }));
 }
 
 fn close_ncurses() {
@@ -0,0 +1,16 @@
+use controller::control::Control;
+use error::Error;
+use sensor::event::Event;
+use std::time::Duration;
+
+/// Everything the master loop reacts to, merged into a single channel so that
+/// it can block until something happens.
+#[derive(Debug)]
+pub enum Message {
+    Control(Control),
+    Event(Event),
+    /// Time elapsed since the previous tick.
+    Tick(Duration),
+    Error(Error),
+    Quit,
+}
@@ -1,96 +1,75 @@
-use controller::control::Control;
-use driver::command::Num;
+use controller::control::{Control, Joystick};
 use driver::command::Command;
+use error::Result;
 use gilrs;
 use gilrs::Button;
-use mode::simulation::Simulation;
-use mode::Mode;
-use std::sync::mpsc;
-use std::time;
-use std::thread;
+use mode;
+use mode::jog::Increment;
+use mode::tension::{self, Adjustment};
+use mode::{Context, Mode};
+use mode::FREQUENCY;
+use sensor::event::Event;
+use state::State;
+use std::time::Duration;
 use std::cmp::min;
+use units::{Millimeters, MillimetersPerMinute, Newtons};
 
 const NUM_MOTORS: usize = 4;
-const FREQUENCY: f32 = 10.0;
-const MAX_SPEED: f32 = 60_000.0;
-const MIN_SPEED: f32 = FREQUENCY * 60.0;
+/// Motors jogged together, selected in turn: each motor alone, the pairs of
+/// motors on each side, then all four for a pure lift.
+const GROUPS: [&'static [usize]; 7] = [&[0], &[1], &[2], &[3], &[0, 1], &[2, 3], &[0, 1, 2, 3]];
+/// Stick of each motor when they are driven independently. The first one
+/// drives the selected group otherwise.
+const STICKS: [gilrs::Axis; NUM_MOTORS] = [
+    gilrs::Axis::LeftStickY,
+    gilrs::Axis::LeftStickX,
+    gilrs::Axis::RightStickY,
+    gilrs::Axis::RightStickX,
+];
+const MAX_SPEED: MillimetersPerMinute = MillimetersPerMinute(60_000.0);
+const MIN_SPEED: MillimetersPerMinute = MillimetersPerMinute(FREQUENCY * 60.0);
 
 #[derive(Debug, Clone, Copy)]
 struct Target {
-    motor: usize,
-    length: f32,
-    speed: f32,
-}
-
-enum Event {
-    Target(Target),
-    SetZero,
-    Quit,
+    /// Index in `GROUPS`.
+    group: usize,
+    /// Deflection of each stick in `STICKS`, from -1 to 1.
+    deflections: [f32; NUM_MOTORS],
+    speed: MillimetersPerMinute,
+    /// Each motor follows its own stick rather than the group the first one.
+    independent: bool,
 }
 
 #[derive(Debug)]
 pub struct Calibration {
-    driver: mpsc::Sender<Command>,
-    thread: mpsc::Sender<Event>,
-    target: Target
-}
-
-fn integrate(
-    rx: mpsc::Receiver<Event>,
-    driver: mpsc::Sender<Command>,
+    context: Context,
     target: Target,
-) {
-    let wait_time: u64 = (1000.0 / FREQUENCY as f64) as u64;
-    let wait_duration = time::Duration::from_millis(wait_time);
-    let mut positions: [f32; NUM_MOTORS] = [0.; NUM_MOTORS];
-    let mut targets: [Target; NUM_MOTORS] = [target; NUM_MOTORS];
-    let mut target = targets[0];
-    let mut command;
-    'emitter: loop {
-
-        for received in rx.try_iter() {
-            match received {
-                Event::Quit => break 'emitter,
-                Event::SetZero => {
-                    positions[target.motor] = 0.;
-                }
-                Event::Target(t) => {
-                    targets[t.motor as usize] = t;
-                    target = targets[t.motor];
-                }
-            }
-        }
-
-        positions[target.motor as usize] += target.length * target.speed / (FREQUENCY * 60.0);
-    
-        command = Command::MoveMotorTo {
-            m: target.motor as Num,
-            l: positions[target.motor] as Num,
-            f: Some(target.speed as Num),
-        };
-        driver.send(command).unwrap();
-        thread::sleep(wait_duration);
-    }
+    positions: [Millimeters; NUM_MOTORS],
+    increment: Increment,
+    /// Whether the tensions reported by the sensors adjust the cables.
+    balancing: bool,
+    /// Time left for the last adjustment to complete and the readings to
+    /// settle, the tensions reported meanwhile are outdated.
+    settling: Duration,
 }
 
-
 impl Mode for Calibration {
-    fn init(driver: &mpsc::Sender<Command>) -> Self {
-        driver.send(Command::SetAbsolute).unwrap();
-        let (tx, rx) = mpsc::channel();
-        let target = Target {
-            motor: 0,
-            length: 0.,
-            speed: 10_000.,
-        };
-        let state = Calibration {
-            driver: driver.clone(),
-            thread: tx,
-            target: target,
-        };
-        let driver = driver.clone();
-        thread::spawn(move || integrate(rx, driver, target));
-        return state;
+    fn init(context: &Context) -> Result<Self> {
+        context.machine.check(State::Homed, "Calibration mode")?;
+        context.driver.send(Command::SetAbsolute)?;
+        Ok(Calibration {
+            context: context.clone(),
+            target: Target {
+                group: 0,
+                deflections: [0.; NUM_MOTORS],
+                speed: MillimetersPerMinute(10_000.),
+                independent: false,
+            },
+            positions: [Millimeters(0.); NUM_MOTORS],
+            increment: Increment::new(),
+            balancing: false,
+            settling: Duration::from_secs(0),
+        })
     }
 
     fn start(&mut self) {
@@ -98,86 +77,196 @@ impl Mode for Calibration {
     }
 
     fn stop(&mut self) {
-        self.thread.send(Event::Quit).unwrap();
+        self.target.deflections = [0.; NUM_MOTORS];
+        self.balancing = false;
+        self.settling = Duration::from_secs(0);
     }
 
     fn name(&self) -> String {
         String::from("Calibration")
     }
 
-    fn next_mode(&self) -> Box<Mode> {
-        Box::new(Simulation::init(&self.driver))
+    fn next_mode(&self) -> Result<Box<Mode>> {
+        mode::next(&self.name(), &self.context)
     }
 
-    fn handle(&mut self, control: Control) {
+    fn handle(&mut self, control: Control) -> Result<()> {
+        if self.increment.handle(&control) {
+            return Ok(());
+        }
         match control {
-            Control::Joystick {
-                event:
-                    gilrs::Event {
-                        id: _,
-                        event,
-                        time: _,
-                    },
-            } => {
-                if let gilrs::EventType::ButtonReleased { 0: button, 1: _ } = event {
-                    self.handle_button(button)
-                } else if let gilrs::EventType::AxisChanged {
-                    0: axis,
-                    1: value,
-                    2: _,
-                } = event
-                {
+            Control::Joystick { event } => {
+                if let Joystick::ButtonReleased(button) = event {
+                    self.handle_button(button)?;
+                } else if let Joystick::AxisChanged(axis, value) = event {
                     self.handle_axis(axis, value)
                 }
             }
-            Control::Keyboard { keycode } => self.handle_key(keycode),
+            Control::Keyboard { keycode } => self.handle_key(keycode)?,
+        }
+        Ok(())
+    }
+
+    fn tick(&mut self, dt: Duration) -> Result<()> {
+        if self.settling > Duration::from_secs(0) {
+            // Sending the targets again at the jog speed would rush the adjustment
+            self.settling = self.settling.checked_sub(dt).unwrap_or(Duration::from_secs(0));
+            return Ok(());
+        }
+        self.integrate(dt)
+    }
+
+    fn release(&mut self) {
+        if self.target.deflections.iter().any(|&deflection| deflection != 0.) {
+            println!(":: Sticks released\r");
+        }
+        self.target.deflections = [0.; NUM_MOTORS];
+    }
+
+    fn notify(&mut self, event: &Event) -> Result<()> {
+        if let Event::Tension { ref tensions } = *event {
+            if self.balancing {
+                return self.balance(tensions);
+            }
         }
+        Ok(())
     }
 }
 
 impl Calibration {
     fn print_state(&mut self) {
-        println!(":: Motor = {} \r", self.target.motor);
-        println!(":: Speed = {} mm/min\r", self.target.speed as Num);
+        let motors: Vec<String> = GROUPS[self.target.group].iter().map(|m| m.to_string()).collect();
+        println!(":: Motors = {} \r", motors.join(", "));
+        if self.target.independent {
+            println!(":: Sticks drive each motor\r");
+        }
+        println!(":: Speed = {}\r", self.target.speed);
         println!("----------\r");
     }
 
-    fn update_target(&mut self) {
-        self.thread.send(Event::Target(self.target.clone())).unwrap();
+    /// Motors driven by the sticks, with their deflection.
+    fn driven(&self) -> Vec<(usize, f32)> {
+        let deflections = &self.target.deflections;
+        if self.target.independent {
+            (0..NUM_MOTORS).map(|motor| (motor, deflections[motor])).collect()
+        } else {
+            GROUPS[self.target.group].iter().map(|&motor| (motor, deflections[0])).collect()
+        }
+    }
+
+    /// Every driven motor gets its target within the same tick, so that they
+    /// move together.
+    fn integrate(&mut self, dt: Duration) -> Result<()> {
+        for (motor, deflection) in self.driven() {
+            self.positions[motor] += self.target.speed.over(dt) * deflection;
+            let command = Command::MoveMotorTo {
+                m: motor,
+                l: self.positions[motor],
+                f: Some(self.target.speed),
+            };
+            self.context.driver.send(command)?;
+        }
+        Ok(())
+    }
+
+    /// Move the selected motors by one increment, as one bounded move each.
+    fn jog(&mut self, direction: f32) -> Result<()> {
+        for &motor in GROUPS[self.target.group].iter() {
+            self.positions[motor] += Millimeters(self.increment.value() * direction);
+            let command = Command::MoveMotorTo {
+                m: motor,
+                l: self.positions[motor],
+                f: Some(self.target.speed),
+            };
+            self.context.driver.send(command)?;
+        }
+        Ok(())
+    }
+
+    fn toggle_balancing(&mut self) {
+        self.balancing = !self.balancing;
+        if self.balancing {
+            println!(":: Balancing the cable tensions\r");
+        } else {
+            println!(":: Tension balancing cancelled\r");
+        }
     }
 
+    fn balance(&mut self, tensions: &[Newtons]) -> Result<()> {
+        if self.settling > Duration::from_secs(0) {
+            return Ok(());
+        }
+        let settings = &self.context.config.tension;
+        match tension::adjust(settings, tensions, NUM_MOTORS)? {
+            Adjustment::Move(changes) => {
+                let longest = changes.iter().map(|&(_, change)| change.0.abs()).fold(0., f32::max);
+                let seconds = longest / settings.speed.0 * 60. + settings.settle;
+                self.settling = Duration::from_millis((seconds * 1000.) as u64);
+                for (motor, change) in changes {
+                    self.positions[motor] += change;
+                    let command = Command::MoveMotorTo {
+                        m: motor,
+                        l: self.positions[motor],
+                        f: Some(settings.speed),
+                    };
+                    self.context.driver.send(command)?;
+                }
+            }
+            Adjustment::Balanced => {
+                self.balancing = false;
+                if self.context.machine.state() == State::Homed {
+                    self.context.machine.set(State::Calibrated);
+                }
+                println!(":: Cables balanced at {}\r", settings.target);
+            }
+            Adjustment::Overload(motor) => {
+                self.balancing = false;
+                // Drop the adjustments still queued on the firmware
+                self.context.driver.send(Command::Shutdown)?;
+                self.context.machine.set(State::Faulted);
+                println!(":: Motor {} overloaded, tension balancing aborted\r", motor);
+            }
+        }
+        Ok(())
+    }
 
-    fn handle_button(&mut self, button: Button) {
+    fn handle_button(&mut self, button: Button) -> Result<()> {
         match button {
+            Button::DPadLeft => return self.jog(-1.),
+            Button::DPadRight => return self.jog(1.),
             Button::DPadUp => {
-                self.target.motor = min(self.target.motor + 1, NUM_MOTORS - 1);
+                self.target.group = min(self.target.group + 1, GROUPS.len() - 1);
             }
             Button::DPadDown => {
-                self.target.motor = if self.target.motor > 0 {self.target.motor - 1} else {0}
+                self.target.group = if self.target.group > 0 {self.target.group - 1} else {0}
             }
+            Button::North => self.target.independent = !self.target.independent,
+            Button::East => self.toggle_balancing(),
             Button::Select => {
-                self.driver.send(Command::SetMotorZero {m: self.target.motor as Num}).unwrap();
-                self.thread.send(Event::SetZero).unwrap();
+                for &motor in GROUPS[self.target.group].iter() {
+                    self.context.driver.send(Command::SetMotorZero {m: motor})?;
+                    self.positions[motor] = Millimeters(0.);
+                }
             }
             _ => ()
         }
         self.print_state();
-        self.update_target();
+        Ok(())
     }
 
     fn handle_axis(&mut self, axis: gilrs::Axis, value: f32) {
         let value = response_curve(value);
-        match axis {
-            gilrs::Axis::LeftStickY => {
-                self.target.length = value;
-            }
-            _ => (),
+        if let Some(stick) = STICKS.iter().position(|&stick| stick == axis) {
+            self.target.deflections[stick] = value;
         }
-        self.update_target();
     }
 
-    fn handle_key(&mut self, keycode: i32) {
+    fn handle_key(&mut self, keycode: i32) -> Result<()> {
         match keycode as u8 as char {
+            ',' => return self.jog(-1.),
+            '.' => return self.jog(1.),
+            'i' => self.target.independent = !self.target.independent,
+            'b' => self.toggle_balancing(),
             'w' => {
                 update_speed(&mut self.target.speed, |x| x * 2., MIN_SPEED, MAX_SPEED);
             },
@@ -187,12 +276,12 @@ impl Calibration {
             _ => ()
         }
         self.print_state();
-        self.update_target();
+        Ok(())
     }
 }
 
-fn update_speed<F>(speed: &mut f32, func: F, min: f32, max: f32) -> f32
-    where F: Fn(f32) -> f32 {
+fn update_speed<Q, F>(speed: &mut Q, func: F, min: Q, max: Q) -> Q
+    where Q: Copy + PartialOrd, F: Fn(Q) -> Q {
     let mut result = func(*speed);
     if result > max { result = max }
     if result < min { result = min }
@@ -0,0 +1,128 @@
+//! Drive a mode without any thread or hardware: controls are scripted on a
+//! simulated clock and the emitted commands are collected for inspection.
+
+use clock::{Clock, SimulatedClock};
+use config::Config;
+use controller::control::Control;
+use driver::command::Command;
+use error::Result;
+use message::Message;
+use mode::{Context, Mode, Supervisor, FREQUENCY};
+use sensor::event::Event;
+use state::{Guard, State, Tracker};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Messages go through the same `Supervisor` as in the master loop, except
+/// that the errors are returned rather than recovered from.
+pub struct Harness {
+    supervisor: Supervisor,
+    commands: mpsc::Receiver<Command>,
+    machine: Tracker,
+    clock: SimulatedClock,
+    last_tick: Duration,
+}
+
+impl Harness {
+    /// Without heartbeats, as there is no firmware to exchange them with.
+    pub fn new<M: Mode + 'static>() -> Result<Harness> {
+        let mut config = Config::default();
+        config.heartbeat.period = 0.;
+        config.heartbeat.timeout = 0.;
+        Harness::with_config::<M>(config)
+    }
+
+    /// The machine is taken as calibrated, see `with_state` otherwise.
+    pub fn with_config<M: Mode + 'static>(config: Config) -> Result<Harness> {
+        Harness::with_state::<M>(config, State::Calibrated)
+    }
+
+    pub fn with_state<M: Mode + 'static>(config: Config, state: State) -> Result<Harness> {
+        let (tx, rx) = mpsc::channel();
+        let machine = Tracker::new(state, false);
+        let context = Context {
+            driver: Guard::new(tx, &machine),
+            config: config,
+            machine: machine.clone(),
+        };
+        let mode = M::init(&context)?;
+        Ok(Harness {
+            supervisor: Supervisor::new(Box::new(mode), &context),
+            commands: rx,
+            machine: machine,
+            clock: SimulatedClock::new(),
+            last_tick: Duration::from_secs(0),
+        })
+    }
+
+    pub fn name(&self) -> String {
+        self.supervisor.name()
+    }
+
+    /// Simulated time elapsed since the mode was created.
+    pub fn now(&self) -> Duration {
+        self.clock.now()
+    }
+
+    /// The clock can be moved by hand to tick at irregular intervals.
+    pub fn clock(&self) -> &SimulatedClock {
+        &self.clock
+    }
+
+    /// The quit trigger is ignored, there is no loop to end.
+    pub fn control(&mut self, control: Control) -> Result<()> {
+        self.supervisor.handle(Message::Control(control)).map(|_| ())
+    }
+
+    pub fn event(&mut self, event: Event) -> Result<()> {
+        self.supervisor.handle(Message::Event(event)).map(|_| ())
+    }
+
+    /// Tick the mode with the time elapsed on the clock since the last tick.
+    pub fn tick(&mut self) -> Result<()> {
+        let now = self.clock.now();
+        let dt = now - self.last_tick;
+        self.last_tick = now;
+        self.supervisor.handle(Message::Tick(dt)).map(|_| ())
+    }
+
+    /// Let `duration` elapse, ticking the mode regularly as the timer would.
+    pub fn advance(&mut self, duration: Duration) -> Result<()> {
+        let end = self.clock.now() + duration;
+        let period = Duration::from_millis((1000.0 / FREQUENCY as f64) as u64);
+        while self.last_tick + period <= end {
+            self.clock.set(self.last_tick + period);
+            self.tick()?;
+        }
+        self.clock.set(end);
+        Ok(())
+    }
+
+    /// Feed each control once the clock reaches its time, relative to the
+    /// creation of the mode.
+    pub fn play(&mut self, script: Vec<(Duration, Control)>) -> Result<()> {
+        for (at, control) in script {
+            let now = self.clock.now();
+            if at > now {
+                self.advance(at - now)?;
+            }
+            self.control(control)?;
+        }
+        Ok(())
+    }
+
+    /// Shared with the modes, e.g. to override it.
+    pub fn machine(&self) -> &Tracker {
+        &self.machine
+    }
+
+    /// Enter the next mode, staying in the current one on error.
+    pub fn next_mode(&mut self) -> Result<()> {
+        self.supervisor.next_mode()
+    }
+
+    /// Commands emitted since the last call.
+    pub fn commands(&mut self) -> Vec<Command> {
+        self.commands.try_iter().collect()
+    }
+}
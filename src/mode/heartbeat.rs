@@ -0,0 +1,68 @@
+//! Heartbeats on the links, so that each side notices when the other one
+//! goes quiet.
+
+use clock::seconds;
+use config;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Beat {
+    /// A heartbeat is due on the driver link.
+    pub send: bool,
+    /// The firmware just went quiet.
+    pub lost: bool,
+}
+
+#[derive(Debug)]
+pub struct Heartbeat {
+    settings: config::Heartbeat,
+    /// Time since the last heartbeat sent.
+    sent: Duration,
+    /// Time since the firmware was last heard of.
+    quiet: Duration,
+    lost: bool,
+}
+
+impl Heartbeat {
+    pub fn new(settings: &config::Heartbeat) -> Heartbeat {
+        Heartbeat {
+            settings: settings.clone(),
+            sent: Duration::from_secs(0),
+            quiet: Duration::from_secs(0),
+            lost: false,
+        }
+    }
+
+    /// Whether the firmware is considered gone.
+    pub fn is_lost(&self) -> bool {
+        self.lost
+    }
+
+    /// Something came from the firmware. Returns whether it was lost.
+    pub fn event(&mut self) -> bool {
+        let lost = self.lost;
+        self.quiet = Duration::from_secs(0);
+        self.lost = false;
+        lost
+    }
+
+    pub fn tick(&mut self, dt: Duration) -> Beat {
+        let period = self.settings.period;
+        self.sent += dt;
+        let send = period > 0. && seconds(self.sent) as f32 >= period;
+        if send {
+            self.sent = Duration::from_secs(0);
+        }
+
+        let timeout = self.settings.timeout;
+        self.quiet += dt;
+        let lost = !self.lost && timeout > 0. && seconds(self.quiet) as f32 > timeout;
+        if lost {
+            self.lost = true;
+        }
+        Beat {
+            send: send,
+            lost: lost,
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! Homing of the machine, to start from a known position rather than an
+//! assumed one.
+
+use clock::seconds;
+use controller::control::{Control, Joystick};
+use driver::command::{Axis, Command, AXES};
+use error::Result;
+use gilrs::Button;
+use mode;
+use mode::{Context, Mode};
+use sensor::event::Event;
+use state::State;
+use std::time::Duration;
+use units::{Degrees, Millimeters, Quantity};
+
+/// Longest wait for the home position once the axes are reported homed, in
+/// seconds. Positions streamed before the home finished may arrive meanwhile.
+const VERIFY_TIMEOUT: f32 = 2.;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Step {
+    Idle,
+    /// Waiting for the firmware to report the axes homed.
+    Moving,
+    /// Waiting for the firmware to report the home position.
+    Verifying,
+    Failed,
+}
+
+#[derive(Debug)]
+pub struct Homing {
+    context: Context,
+    step: Step,
+    /// Axes not reported homed yet.
+    pending: Vec<Axis>,
+    /// Time spent waiting in the current step.
+    elapsed: Duration,
+    /// Last position reported away from home while verifying.
+    away: Option<(Millimeters, Millimeters, Millimeters)>,
+}
+
+impl Mode for Homing {
+    fn init(context: &Context) -> Result<Self> {
+        Ok(Homing {
+            context: context.clone(),
+            step: Step::Idle,
+            pending: Vec::new(),
+            elapsed: Duration::from_secs(0),
+            away: None,
+        })
+    }
+
+    fn start(&mut self) {
+        self.print_state();
+    }
+
+    fn stop(&mut self) {}
+
+    fn name(&self) -> String {
+        String::from("Homing")
+    }
+
+    fn next_mode(&self) -> Result<Box<Mode>> {
+        mode::next(&self.name(), &self.context)
+    }
+
+    fn handle(&mut self, control: Control) -> Result<()> {
+        match control {
+            Control::Joystick {
+                event: Joystick::ButtonReleased(Button::South),
+            } => self.home(),
+            Control::Keyboard { keycode } if keycode == 'h' as i32 => self.home(),
+            _ => Ok(()),
+        }
+    }
+
+    fn tick(&mut self, dt: Duration) -> Result<()> {
+        if self.step != Step::Moving && self.step != Step::Verifying {
+            return Ok(());
+        }
+        self.elapsed += dt;
+        let mut timeout = self.context.config.homing.timeout;
+        if self.step == Step::Verifying {
+            timeout = timeout.min(VERIFY_TIMEOUT);
+        }
+        if seconds(self.elapsed) as f32 > timeout {
+            let reason = match (self.step, self.away) {
+                (Step::Moving, _) => {
+                    let letters: Vec<String> = self.pending.iter().map(|axis| axis.letter().to_string()).collect();
+                    format!("timed out waiting for {}", letters.join(", "))
+                }
+                (_, Some((x, y, z))) => format!("position X {} Y {} Z {} after homing", x.0, y.0, z.0),
+                (_, None) => String::from("timed out waiting for the position"),
+            };
+            return self.fail(&reason);
+        }
+        Ok(())
+    }
+
+    fn notify(&mut self, event: &Event) -> Result<()> {
+        match *event {
+            Event::Homed { ref axes } if self.step == Step::Moving => {
+                self.pending.retain(|axis| !axes.contains(axis));
+                if self.pending.is_empty() {
+                    self.step = Step::Verifying;
+                    self.elapsed = Duration::from_secs(0);
+                    self.away = None;
+                    self.context.driver.send(Command::QueryPosition)?;
+                }
+                Ok(())
+            }
+            // Only positions reported after the axes are homed, and at home,
+            // as those streamed while moving can still be on their way
+            Event::Position { x, y, z, u, v, .. } if self.step == Step::Verifying => {
+                let settings = &self.context.config.homing;
+                let home = |value: f32, index: usize| (value - settings.position[index]).abs() <= settings.tolerance;
+                let angle = |value: Option<Degrees>, index| value.map(|value| home(value.value(), index)).unwrap_or(true);
+                if home(x.0, 0) && home(y.0, 1) && home(z.0, 2) && angle(u, 3) && angle(v, 4) {
+                    self.step = Step::Idle;
+                    self.context.machine.set(State::Homed);
+                    println!(":: Motion unlocked\r");
+                } else {
+                    self.away = Some((x, y, z));
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Homing {
+    fn print_state(&mut self) {
+        println!(":: Machine {:?}, press h or A to home\r", self.context.machine.state());
+        println!("----------\r");
+    }
+
+    fn home(&mut self) -> Result<()> {
+        if self.step == Step::Moving || self.step == Step::Verifying {
+            return Ok(());
+        }
+        self.context.driver.send(Command::MoveToHome { axes: vec![] })?;
+        self.context.machine.set(State::Unhomed);
+        self.step = Step::Moving;
+        self.pending = AXES.to_vec();
+        self.elapsed = Duration::from_secs(0);
+        println!(":: Homing\r");
+        Ok(())
+    }
+
+    /// Halt the machine, which stays locked until homed again.
+    fn fail(&mut self, reason: &str) -> Result<()> {
+        self.step = Step::Failed;
+        self.context.machine.set(State::Faulted);
+        println!(":: Homing failed: {}\r", reason);
+        self.context.driver.send(Command::Shutdown)?;
+        Ok(())
+    }
+}
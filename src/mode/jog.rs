@@ -0,0 +1,46 @@
+//! Discrete jogs, moving by a fixed increment on each press.
+
+use controller::control::{Control, Joystick};
+use gilrs::Button;
+
+/// Selectable increments, in mm or degrees.
+pub const INCREMENTS: [f32; 4] = [0.1, 1., 10., 100.];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Increment {
+    index: usize,
+}
+
+impl Increment {
+    /// Starts at 1 mm or degree.
+    pub fn new() -> Increment {
+        Increment { index: 1 }
+    }
+
+    pub fn value(&self) -> f32 {
+        INCREMENTS[self.index]
+    }
+
+    /// Select a finer increment on the left bumper or `[`, a coarser one on
+    /// the right bumper or `]`. Returns whether the control was used.
+    pub fn handle(&mut self, control: &Control) -> bool {
+        let finer = match *control {
+            Control::Joystick {
+                event: Joystick::ButtonReleased(Button::LeftTrigger),
+            } => true,
+            Control::Joystick {
+                event: Joystick::ButtonReleased(Button::RightTrigger),
+            } => false,
+            Control::Keyboard { keycode } if keycode == '[' as i32 => true,
+            Control::Keyboard { keycode } if keycode == ']' as i32 => false,
+            _ => return false,
+        };
+        if finer && self.index > 0 {
+            self.index -= 1;
+        } else if !finer && self.index < INCREMENTS.len() - 1 {
+            self.index += 1;
+        }
+        println!(":: Jog increment = {}\r", self.value());
+        true
+    }
+}
@@ -1,31 +1,27 @@
-use controller::control::Control;
-use driver::command::Command;
-use driver::command::Num;
+use clock::seconds;
+use controller::control::{Control, Joystick};
+use driver::command::{self, Command};
+use error::{Context as ErrorContext, Result};
+use frame::{Attitude, Machine, Platform, Vector, World};
 use gilrs;
 use gilrs::Button;
-use mode::calibration::Calibration;
-use mode::Mode;
-use na::{Vector3, Vector2};
-use std::sync::mpsc;
-use std::thread;
-use std::time;
-
-const FREQUENCY: f32 = 10.0;
-const MAX_TRANSLATION_SPEED: f32 = 60_000.0;
-const MIN_TRANSLATION_SPEED: f32 = FREQUENCY * 60.0;
-const MAX_ROTATION_SPEED: f32 = 2_700.0;
-const MIN_ROTATION_SPEED: f32 = FREQUENCY * 60.0;
+use mode;
+use mode::jog::Increment;
+use mode::waypoint::{self, Waypoint};
+use mode::{Context, Mode};
+use mode::FREQUENCY;
+use planner::Profile;
+use sensor::event::Event;
+use state::State;
+use std::fs::File;
+use std::io::Write;
+use std::time::Duration;
+use units::{Degrees, DegreesPerMinute, Millimeters, MillimetersPerMinute, Quantity};
 
-#[derive(Debug, Clone)]
-struct Target {
-    axis: Axis<f32>,
-    speed: Speed<f32>,
-}
-
-enum Event {
-    Target(Target),
-    Quit,
-}
+const MAX_TRANSLATION_SPEED: MillimetersPerMinute = MillimetersPerMinute(60_000.0);
+const MIN_TRANSLATION_SPEED: MillimetersPerMinute = MillimetersPerMinute(FREQUENCY * 60.0);
+const MAX_ROTATION_SPEED: DegreesPerMinute = DegreesPerMinute(2_700.0);
+const MIN_ROTATION_SPEED: DegreesPerMinute = DegreesPerMinute(FREQUENCY * 60.0);
 
 #[derive(Debug, Clone)]
 struct Axis<T> {
@@ -37,70 +33,48 @@ struct Axis<T> {
 }
 
 #[derive(Debug, Clone)]
-struct Speed<T> {
-    translational: T,
-    rotational: T,
+struct Speed {
+    translational: MillimetersPerMinute,
+    rotational: DegreesPerMinute,
 }
 
 #[derive(Debug)]
 pub struct Manual {
-    driver: mpsc::Sender<Command>,
-    thread: mpsc::Sender<Event>,
+    context: Context,
     axis: Axis<f32>,
-    speed: Speed<f32>,
-}
-
-fn integrate(
-    rx: mpsc::Receiver<Event>,
-    driver: mpsc::Sender<Command>,
-    target: Target,
-) {
-    let wait_time: u64 = (1000.0 / FREQUENCY as f64) as u64;
-    let wait_duration = time::Duration::from_millis(wait_time);
-    let mut target = target;
-    let mut position = Vector3::new(1350., 1800., 400.);
-    let mut rotation = Vector2::new(0., 0.);
-    let mut command;
-    'emitter: loop {
-        for received in rx.try_iter() {
-            match received {
-                Event::Quit => break 'emitter,
-                Event::Target(t) => {
-                    target = t;
-                }
-            }
-        }
-
-        position.x += target.axis.x * target.speed.translational as f32 / (FREQUENCY * 60.0);
-        position.y += target.axis.y * target.speed.translational as f32 / (FREQUENCY * 60.0);
-        position.z += target.axis.z * target.speed.translational as f32 / (FREQUENCY * 60.0);
-        rotation.x += target.axis.u * target.speed.rotational as f32 / (FREQUENCY * 60.0);
-        rotation.y += target.axis.v * target.speed.rotational as f32 / (FREQUENCY * 60.0);
-    
-        command = Command::MoveTo {
-            x: Some(position.x as Num),
-            y: Some(position.y as Num),
-            z: Some(position.z as Num),
-            f: Some(target.speed.translational as Num),
-        };
-        driver.send(command).unwrap();
-        command = Command::RotateTo {
-            u: Some(rotation.x as Num),
-            v: Some(rotation.y as Num),
-            f: Some(target.speed.rotational as Num),
-        };
-        driver.send(command).unwrap();
-        thread::sleep(wait_duration);
-    }
+    speed: Speed,
+    profiles: Axis<Profile>,
+    position: Vector<World, Millimeters>,
+    rotation: Attitude,
+    /// Whether `position` matches the machine, either reported by the
+    /// firmware or driven in absolute coordinates, see `config::Manual::sync`.
+    synced: bool,
+    increment: Increment,
+    /// Translations follow the platform attitude rather than the world axes.
+    platform_frame: bool,
+    /// Poses taught so far, slot 1 being the first one.
+    waypoints: Vec<Waypoint>,
+    /// Poses on the way to a waypoint, sampled every nominal tick from the
+    /// one the go-to started from.
+    trajectory: Vec<Waypoint>,
+    /// Time elapsed along `trajectory`.
+    travelled: Duration,
 }
 
 impl Mode for Manual {
-    fn init(driver: &mpsc::Sender<Command>) -> Self {
-        driver.send(Command::SetAbsolute).unwrap();
-        let (tx, rx) = mpsc::channel();
-        let state = Manual {
-            driver: driver.clone(),
-            thread: tx,
+    fn init(context: &Context) -> Result<Self> {
+        context.machine.check(State::Calibrated, "Manual mode")?;
+        let settings = &context.config.manual;
+        if settings.relative {
+            context.driver.send(Command::SetRelative)?;
+        } else {
+            context.driver.send(Command::SetAbsolute)?;
+        }
+        if settings.sync {
+            context.driver.send(Command::QueryPosition)?;
+        }
+        Ok(Manual {
+            context: context.clone(),
             axis: Axis {
                 x: 0.,
                 y: 0.,
@@ -109,17 +83,26 @@ impl Mode for Manual {
                 v: 0.,
             },
             speed: Speed {
-                translational: 6000.,
-                rotational: 2000.,
-            }
-        };
-        let driver = driver.clone();
-        let target = Target {
-            axis: state.axis.clone(),
-            speed: state.speed.clone(),
-        };
-        thread::spawn(move || integrate(rx, driver, target));
-        return state;
+                translational: MillimetersPerMinute(6000.),
+                rotational: DegreesPerMinute(2000.),
+            },
+            profiles: Axis {
+                x: Profile::new(),
+                y: Profile::new(),
+                z: Profile::new(),
+                u: Profile::new(),
+                v: Profile::new(),
+            },
+            position: Vector::new(Millimeters(1350.), Millimeters(1800.), Millimeters(400.)),
+            rotation: Attitude::default(),
+            // Relative moves leave the machine wherever it was
+            synced: !settings.sync && !settings.relative,
+            increment: Increment::new(),
+            platform_frame: false,
+            waypoints: Vec::new(),
+            trajectory: Vec::new(),
+            travelled: Duration::from_secs(0),
+        })
     }
 
     fn start(&mut self) {
@@ -127,56 +110,342 @@ impl Mode for Manual {
     }
 
     fn stop(&mut self) {
-        self.thread.send(Event::Quit).unwrap();
+        self.axis = Axis {
+            x: 0.,
+            y: 0.,
+            z: 0.,
+            u: 0.,
+            v: 0.,
+        };
+        self.trajectory.clear();
     }
 
     fn name(&self) -> String {
         String::from("Manual")
     }
 
-    fn next_mode(&self) -> Box<Mode> {
-        Box::new(Calibration::init(&self.driver))
+    fn next_mode(&self) -> Result<Box<Mode>> {
+        mode::next(&self.name(), &self.context)
     }
 
-    fn handle(&mut self, control: Control) {
+    fn handle(&mut self, control: Control) -> Result<()> {
+        if self.increment.handle(&control) {
+            return Ok(());
+        }
         match control {
-            Control::Joystick {
-                event:
-                    gilrs::Event {
-                        id: _,
-                        event,
-                        time: _,
-                    },
-            } => {
-                if let gilrs::EventType::ButtonChanged { 0: button, 1: value, 2: _ } = event {
+            Control::Joystick { event } => {
+                if let Joystick::ButtonChanged(button, value) = event {
                     self.handle_button(button, value)
-                } else if let gilrs::EventType::AxisChanged {
-                    0: axis,
-                    1: value,
-                    2: _,
-                } = event
-                {
+                } else if let Joystick::ButtonReleased(button) = event {
+                    self.handle_released(button)?
+                } else if let Joystick::AxisChanged(axis, value) = event {
                     self.handle_axis(axis, value)
                 }
             }
-            Control::Keyboard { keycode } => self.handle_key(keycode),
+            Control::Keyboard { keycode } => self.handle_key(keycode)?,
         }
+        Ok(())
+    }
+
+    fn tick(&mut self, dt: Duration) -> Result<()> {
+        self.integrate(dt)
+    }
+
+    fn release(&mut self) {
+        let axis = &self.axis;
+        if axis.x != 0. || axis.y != 0. || axis.z != 0. || axis.u != 0. || axis.v != 0. {
+            println!(":: Sticks released\r");
+        }
+        if !self.trajectory.is_empty() {
+            println!(":: Waypoint go-to cancelled\r");
+        }
+        self.axis = Axis {
+            x: 0.,
+            y: 0.,
+            z: 0.,
+            u: 0.,
+            v: 0.,
+        };
+        self.trajectory.clear();
+    }
+
+    fn notify(&mut self, event: &Event) -> Result<()> {
+        if let Event::Position { x, y, z, u, v, .. } = *event {
+            if !self.synced {
+                let position: Vector<Machine, Millimeters> = Vector::new(x, y, z);
+                self.position = position.to_world();
+                self.rotation.u = u.unwrap_or(self.rotation.u);
+                self.rotation.v = v.unwrap_or(self.rotation.v);
+                self.synced = true;
+                println!(":: Position synced\r");
+            }
+        }
+        Ok(())
     }
 }
 
 impl Manual {
     fn print_state(&mut self) {
-        println!(":: Translation speed = {} mm/min\r", self.speed.translational as Num);
-        println!(":: Rotation speed = {} deg/min\r", self.speed.rotational as Num);
+        println!(":: Translation speed = {}\r", self.speed.translational);
+        println!(":: Rotation speed = {}\r", self.speed.rotational);
+        println!(":: Frame = {}\r", if self.platform_frame { "platform" } else { "world" });
         println!("----------\r");
     }
 
-    fn update_target(&mut self) {
-        let target = Target {
-            axis: self.axis.clone(),
-            speed: self.speed.clone(),
+    fn integrate(&mut self, dt: Duration) -> Result<()> {
+        let axis = &self.axis;
+        let idle = axis.x == 0. && axis.y == 0. && axis.z == 0. && axis.u == 0. && axis.v == 0.;
+        if !idle && !self.trajectory.is_empty() {
+            println!(":: Waypoint move interrupted\r");
+            self.trajectory.clear();
+        }
+        if !self.trajectory.is_empty() {
+            // Followed by the time elapsed rather than by the ticks, which may jitter
+            self.travelled += dt;
+            let waypoint = waypoint::at(&self.trajectory, sampling(), self.travelled);
+            if self.travelled >= waypoint::duration(&self.trajectory, sampling()) {
+                self.trajectory.clear();
+            }
+            let step = waypoint.position - self.position;
+            let turn = Attitude {
+                u: waypoint.rotation.u - self.rotation.u,
+                v: waypoint.rotation.v - self.rotation.v,
+            };
+            self.position = waypoint.position;
+            self.rotation = waypoint.rotation;
+            return self.send(step, turn);
+        }
+
+        // The stick deflections are velocity targets, reached within the planner limits
+        let limits = &self.context.config.planner;
+        let translational = self.speed.translational.per_second();
+        let rotational = self.speed.rotational.per_second();
+        let seconds = seconds(dt) as f32;
+        let deflection = self.to_world(Vector::new(self.axis.x, self.axis.y, self.axis.z));
+        let velocity: Vector<World, f32> = Vector::new(
+            self.profiles.x.track(deflection.x * translational, &limits.translation, dt),
+            self.profiles.y.track(deflection.y * translational, &limits.translation, dt),
+            self.profiles.z.track(deflection.z * translational, &limits.translation, dt),
+        );
+        let step = velocity.map(|v| Millimeters(v * seconds));
+        let turn = Attitude {
+            u: Degrees(self.profiles.u.track(self.axis.u * rotational, &limits.rotation, dt) * seconds),
+            v: Degrees(self.profiles.v.track(self.axis.v * rotational, &limits.rotation, dt) * seconds),
         };
-        self.thread.send(Event::Target(target)).unwrap();
+        self.position += step;
+        self.rotation.u += turn.u;
+        self.rotation.v += turn.v;
+        self.send(step, turn)
+    }
+
+    /// Send the motion of this tick, `position` and `rotation` being updated.
+    fn send(&mut self, step: Vector<World, Millimeters>, turn: Attitude) -> Result<()> {
+        if self.context.config.manual.relative {
+            self.move_by(step.to_machine(), turn)
+        } else if self.synced {
+            self.move_to()
+        } else {
+            // Moving from a guessed position could drive the machine anywhere
+            Ok(())
+        }
+    }
+
+    fn move_to(&mut self) -> Result<()> {
+        self.context.driver.send(Command::move_to(self.position.to_machine(), self.speed.translational))?;
+        let command = Command::RotateTo {
+            u: Some(self.rotation.u),
+            v: Some(self.rotation.v),
+            f: Some(self.speed.rotational),
+        };
+        self.context.driver.send(command)?;
+        Ok(())
+    }
+
+    /// Send the increments of this tick, the machine being in relative mode.
+    fn move_by(&mut self, step: Vector<Machine, Millimeters>, turn: Attitude) -> Result<()> {
+        let zero = Millimeters(0.);
+        if step.x != zero || step.y != zero || step.z != zero {
+            self.context.driver.send(Command::move_to(step, self.speed.translational))?;
+        }
+        if turn != Attitude::default() {
+            let command = Command::RotateTo {
+                u: Some(turn.u),
+                v: Some(turn.v),
+                f: Some(self.speed.rotational),
+            };
+            self.context.driver.send(command)?;
+        }
+        Ok(())
+    }
+
+    /// Inputs are given along the platform axes, which only match the world
+    /// ones when the platform frame is off.
+    fn to_world<U: Quantity>(&self, vector: Vector<Platform, U>) -> Vector<World, U> {
+        if self.platform_frame {
+            vector.to_world(&self.rotation)
+        } else {
+            Vector::new(vector.x, vector.y, vector.z)
+        }
+    }
+
+    /// Move by one increment along a single axis, as one bounded move.
+    fn jog(&mut self, axis: command::Axis, direction: f32) -> Result<()> {
+        let relative = self.context.config.manual.relative;
+        if !relative && !self.synced {
+            println!(":: Position not synced yet, jog ignored\r");
+            return Ok(());
+        }
+        let distance = self.increment.value() * direction;
+        let zero = Millimeters(0.);
+        let mut step: Vector<Platform, Millimeters> = Vector::new(zero, zero, zero);
+        let mut turn = Attitude::default();
+        match axis {
+            command::Axis::X => step.x = Millimeters(distance),
+            command::Axis::Y => step.y = Millimeters(distance),
+            command::Axis::Z => step.z = Millimeters(distance),
+            command::Axis::U => turn.u = Degrees(distance),
+            command::Axis::V => turn.v = Degrees(distance),
+        }
+
+        let command = match axis {
+            command::Axis::X | command::Axis::Y | command::Axis::Z => {
+                let step = self.to_world(step);
+                self.position += step;
+                let target = if relative { step } else { self.position };
+                Command::move_to(target.to_machine(), self.speed.translational)
+            }
+            command::Axis::U | command::Axis::V => {
+                self.rotation.u += turn.u;
+                self.rotation.v += turn.v;
+                let target = if relative { turn } else { self.rotation };
+                Command::RotateTo {
+                    u: Some(target.u),
+                    v: Some(target.v),
+                    f: Some(self.speed.rotational),
+                }
+            }
+        };
+        self.context.driver.send(command)?;
+        Ok(())
+    }
+
+    /// Remember the current pose in the next slot.
+    fn teach(&mut self) {
+        if !self.synced {
+            println!(":: Position not synced yet, waypoint not saved\r");
+            return;
+        }
+        self.waypoints.push(Waypoint {
+            position: self.position,
+            rotation: self.rotation,
+        });
+        println!(":: Waypoint {} saved\r", self.waypoints.len());
+    }
+
+    fn list_waypoints(&self) {
+        for (index, waypoint) in self.waypoints.iter().enumerate() {
+            let position = &waypoint.position;
+            let rotation = &waypoint.rotation;
+            println!(
+                ":: Waypoint {}: X {} Y {} Z {} U {} V {}\r",
+                index + 1,
+                position.x.0,
+                position.y.0,
+                position.z.0,
+                rotation.u.0,
+                rotation.v.0
+            );
+        }
+        if self.waypoints.is_empty() {
+            println!(":: No waypoint saved\r");
+        }
+    }
+
+    /// Plan the way to the waypoint in `slot`, counted from 1.
+    fn go_to(&mut self, slot: usize) -> Result<()> {
+        if !self.context.config.manual.relative && !self.synced {
+            println!(":: Position not synced yet, waypoint ignored\r");
+            return Ok(());
+        }
+        let target = match self.waypoints.get(slot.wrapping_sub(1)) {
+            Some(waypoint) => *waypoint,
+            None => {
+                println!(":: No waypoint {}\r", slot);
+                return Ok(());
+            }
+        };
+        let current = Waypoint {
+            position: self.position,
+            rotation: self.rotation,
+        };
+        let trajectory = waypoint::trajectory(
+            &current,
+            &target,
+            self.speed.translational,
+            self.speed.rotational,
+            &self.context.config.planner,
+            sampling(),
+        );
+        // The planned poses start one tick in
+        self.trajectory = vec![current];
+        self.trajectory.extend(trajectory);
+        self.travelled = Duration::from_secs(0);
+        println!(":: Moving to waypoint {}\r", slot);
+        Ok(())
+    }
+
+    /// Go to the waypoint after the one reached last, wrapping around.
+    fn go_to_next(&mut self) -> Result<()> {
+        let current = self.waypoints.iter().position(|waypoint| {
+            waypoint.position == self.position && waypoint.rotation == self.rotation
+        });
+        let slot = match current {
+            Some(index) => (index + 1) % self.waypoints.len() + 1,
+            None => 1,
+        };
+        self.go_to(slot)
+    }
+
+    fn export(&self) -> Result<()> {
+        // The program drives the machine to absolute coordinates
+        if !self.synced {
+            println!(":: Position not synced yet, waypoints not exported\r");
+            return Ok(());
+        }
+        let program = waypoint::program(
+            &self.waypoints,
+            self.speed.translational,
+            self.speed.rotational,
+            self.context.config.driver.precision,
+        )?;
+        let path = &self.context.config.manual.program;
+        File::create(path)
+            .and_then(|mut file| file.write_all(program.as_bytes()))
+            .context("writing waypoint program")?;
+        println!(":: {} waypoints exported to {}\r", self.waypoints.len(), path);
+        Ok(())
+    }
+
+    fn handle_released(&mut self, button: Button) -> Result<()> {
+        match button {
+            Button::Select => {
+                self.platform_frame = !self.platform_frame;
+                self.print_state();
+                Ok(())
+            }
+            Button::DPadLeft => self.jog(command::Axis::X, -1.),
+            Button::DPadRight => self.jog(command::Axis::X, 1.),
+            Button::DPadDown => self.jog(command::Axis::Y, -1.),
+            Button::DPadUp => self.jog(command::Axis::Y, 1.),
+            Button::South => self.jog(command::Axis::Z, -1.),
+            Button::North => self.jog(command::Axis::Z, 1.),
+            Button::East => {
+                self.teach();
+                Ok(())
+            }
+            Button::West => self.go_to_next(),
+            _ => Ok(()),
+        }
     }
 
     fn handle_button(&mut self, button: Button, value: f32) {
@@ -190,7 +459,6 @@ impl Manual {
             }
             _ => (),
         }
-        self.update_target();
     }
 
     fn handle_axis(&mut self, axis: gilrs::Axis, value: f32) {
@@ -210,11 +478,29 @@ impl Manual {
             }
             _ => (),
         }
-        self.update_target();
     }
 
-    fn handle_key(&mut self, keycode: i32) {
-        match keycode as u8 as char {
+    fn handle_key(&mut self, keycode: i32) -> Result<()> {
+        let key = keycode as u8 as char;
+        // Lower case jogs backwards, upper case forwards
+        if let Some(axis) = command::Axis::from_letter(key.to_ascii_uppercase()) {
+            let direction = if key.is_ascii_uppercase() { 1. } else { -1. };
+            return self.jog(axis, direction);
+        }
+        if let Some(slot) = key.to_digit(10) {
+            return self.go_to(slot as usize);
+        }
+        match key {
+            't' => {
+                self.teach();
+                return Ok(());
+            }
+            'p' => {
+                self.list_waypoints();
+                return Ok(());
+            }
+            'g' => return self.export(),
+            'f' => self.platform_frame = !self.platform_frame,
             'w' => {
                 update_speed(&mut self.speed.translational, |x| x * 2., MIN_TRANSLATION_SPEED, MAX_TRANSLATION_SPEED);
             },
@@ -222,10 +508,10 @@ impl Manual {
                 update_speed(&mut self.speed.translational, |x| x / 2., MIN_TRANSLATION_SPEED, MAX_TRANSLATION_SPEED);
             },
             'a' => {
-                update_speed(&mut self.speed.translational, |x| x - 100., MIN_TRANSLATION_SPEED, MAX_TRANSLATION_SPEED);
+                update_speed(&mut self.speed.translational, |x| x - MillimetersPerMinute(100.), MIN_TRANSLATION_SPEED, MAX_TRANSLATION_SPEED);
             },
             'd' => {
-                update_speed(&mut self.speed.translational, |x| x + 100., MIN_TRANSLATION_SPEED, MAX_TRANSLATION_SPEED);
+                update_speed(&mut self.speed.translational, |x| x + MillimetersPerMinute(100.), MIN_TRANSLATION_SPEED, MAX_TRANSLATION_SPEED);
             },
             'i' => {
                 update_speed(&mut self.speed.rotational, |x| x * 2., MIN_ROTATION_SPEED, MAX_ROTATION_SPEED);
@@ -234,20 +520,20 @@ impl Manual {
                 update_speed(&mut self.speed.rotational, |x| x / 2., MIN_ROTATION_SPEED, MAX_ROTATION_SPEED);
             },
             'j' => {
-                update_speed(&mut self.speed.rotational, |x| x - 100., MIN_ROTATION_SPEED, MAX_ROTATION_SPEED);
+                update_speed(&mut self.speed.rotational, |x| x - DegreesPerMinute(100.), MIN_ROTATION_SPEED, MAX_ROTATION_SPEED);
             },
             'l' => {
-                update_speed(&mut self.speed.rotational, |x| x + 100., MIN_ROTATION_SPEED, MAX_ROTATION_SPEED);
+                update_speed(&mut self.speed.rotational, |x| x + DegreesPerMinute(100.), MIN_ROTATION_SPEED, MAX_ROTATION_SPEED);
             },
             _ => ()
         }
         self.print_state();
-        self.update_target();
+        Ok(())
     }
 }
 
-fn update_speed<F>(speed: &mut f32, func: F, min: f32, max: f32) -> f32
-    where F: Fn(f32) -> f32 {
+fn update_speed<Q, F>(speed: &mut Q, func: F, min: Q, max: Q) -> Q
+    where Q: Copy + PartialOrd, F: Fn(Q) -> Q {
     let mut result = func(*speed);
     if result > max { result = max }
     if result < min { result = min }
@@ -262,4 +548,9 @@ fn response_curve(value: f32) -> f32 {
     }
     value = value.powf(3.);
     return value
-}
\ No newline at end of file
+}
+
+/// Interval between the poses of the waypoint trajectories, the nominal tick.
+fn sampling() -> Duration {
+    Duration::from_millis((1000.0 / FREQUENCY as f64) as u64)
+}
@@ -1,88 +1,325 @@
-mod calibration;
-mod manual;
-mod simulation;
+pub mod calibration;
+pub mod harness;
+pub mod heartbeat;
+pub mod homing;
+pub mod jog;
+pub mod manual;
+pub mod simulation;
+pub mod tension;
+pub mod watchdog;
+pub mod waypoint;
 
-use controller::control::Control;
+use config::{Action, Config};
+use controller::control::{Control, Joystick};
 use driver::command::Command;
+use error::{Error, Result};
 use gilrs;
+use message::Message;
+use mode::calibration::Calibration;
+use mode::heartbeat::{Beat, Heartbeat};
+use mode::homing::Homing;
 use mode::manual::Manual;
+use mode::simulation::Simulation;
+use mode::watchdog::Watchdog;
+use recorder::{Entry, Recorder};
 use sensor::event::Event;
+use state::{Guard, State, Tracker};
 use std::sync::mpsc;
-use std::{thread, time};
+use std::time::Duration;
 
-trait Mode {
-    fn init(driver: &mpsc::Sender<Command>) -> Self
+/// Frequency at which the modes are ticked, in Hz.
+pub const FREQUENCY: f32 = 10.0;
+
+/// Modes in the order the mode trigger goes through them, by name.
+const CYCLE: [(&'static str, fn(&Context) -> Result<Box<Mode>>); 4] = [
+    ("Manual", enter::<Manual>),
+    ("Calibration", enter::<Calibration>),
+    ("Simulation", enter::<Simulation>),
+    ("Homing", enter::<Homing>),
+];
+
+/// Gamepad buttons acted on in some mode, which the hold-to-move button
+/// cannot be, see `config::Watchdog::hold`.
+pub const BUTTONS: [gilrs::Button; 15] = [
+    gilrs::Button::Mode,
+    gilrs::Button::Start,
+    gilrs::Button::Select,
+    gilrs::Button::South,
+    gilrs::Button::East,
+    gilrs::Button::North,
+    gilrs::Button::West,
+    gilrs::Button::DPadUp,
+    gilrs::Button::DPadDown,
+    gilrs::Button::DPadLeft,
+    gilrs::Button::DPadRight,
+    gilrs::Button::LeftTrigger,
+    gilrs::Button::RightTrigger,
+    gilrs::Button::LeftTrigger2,
+    gilrs::Button::RightTrigger2,
+];
+
+/// What every mode needs to drive the machine, handed over from mode to mode.
+#[derive(Debug, Clone)]
+pub struct Context {
+    pub driver: Guard,
+    pub config: Config,
+    pub machine: Tracker,
+}
+
+pub trait Mode {
+    fn init(context: &Context) -> Result<Self>
     where
         Self: Sized;
     fn name(&self) -> String;
     fn start(&mut self);
     fn stop(&mut self);
-    fn next_mode(&self) -> Box<Mode>;
-    fn handle(&mut self, control: Control);
+    /// Mode entered on the mode trigger, usually `next`.
+    fn next_mode(&self) -> Result<Box<Mode>>;
+    fn handle(&mut self, control: Control) -> Result<()>;
+    fn tick(&mut self, dt: Duration) -> Result<()>;
+    /// Let go of the sticks and of whatever else moves unattended, e.g. when
+    /// the gamepad is gone.
+    fn release(&mut self) {}
+    /// Sensor events, e.g. the position reported by the firmware.
+    fn notify(&mut self, _event: &Event) -> Result<()> {
+        Ok(())
+    }
+}
+
+fn enter<M: Mode + 'static>(context: &Context) -> Result<Box<Mode>> {
+    Ok(Box::new(M::init(context)?))
+}
+
+/// The mode following `current` in the cycle, skipping those the machine is
+/// not ready for, so that Homing can always be reached. Fails with
+/// `Error::NotReady` when the cycle comes back to `current` first.
+pub fn next(current: &str, context: &Context) -> Result<Box<Mode>> {
+    let index = CYCLE.iter().position(|&(name, _)| name == current).unwrap_or(0);
+    let mut skipped = None;
+    for offset in 1..CYCLE.len() {
+        let (_, init) = CYCLE[(index + offset) % CYCLE.len()];
+        match init(context) {
+            Err(error @ Error::NotReady { .. }) => {
+                println!(":: {}\r", error);
+                skipped = skipped.or(Some(error));
+            }
+            result => return result,
+        }
+    }
+    Err(skipped.unwrap_or(Error::NotReady {
+        context: format!("no mode after {}", current),
+    }))
 }
 
 pub fn master_loop(
-    controller: mpsc::Receiver<Control>,
-    driver: mpsc::Sender<Command>,
-    sensor: mpsc::Receiver<Event>,
-) {
-    let mut mode: Box<Mode> = Box::new(Manual::init(&driver));
+    messages: mpsc::Receiver<Message>,
+    context: Context,
+    recorder: Recorder,
+) -> Result<()> {
+    let mode: Box<Mode> = match Manual::init(&context) {
+        Ok(manual) => Box::new(manual),
+        // Nothing can move before the machine is homed and calibrated
+        Err(Error::NotReady { .. }) => Box::new(Homing::init(&context)?),
+        Err(error) => return Err(error),
+    };
+    recorder.record(Entry::Config(context.config.clone()));
     println!(":: Welcome to FSSP\r");
     println!(":: Mode: {}\r", mode.name());
-    mode.start();
-    loop {
-        if !handle_controls(&controller, &mut mode) {
-            break;
-        };
-        handle_events(&sensor, &mut mode, &driver);
-        thread::yield_now();
+    let mut supervisor = Supervisor::new(mode, &context);
+    for message in messages.iter() {
+        record(&recorder, &message);
+        match supervisor.handle(message).or_else(|error| recover(error).map(|()| true)) {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(error) => {
+                supervisor.stop();
+                return Err(error);
+            }
+        }
     }
+    supervisor.stop();
+    Ok(())
 }
 
-fn handle_controls(controller: &mpsc::Receiver<Control>, mode: &mut Box<Mode>) -> bool {
-    for control in controller.try_iter() {
-        // Handle quit trigger
-        if is_quit_trigger(&control) {
-            return false;
+/// The current mode, behind the watchdog on the controls and the heartbeat
+/// with the firmware. Drives the mode for the master loop and the harness.
+pub struct Supervisor {
+    mode: Box<Mode>,
+    context: Context,
+    watchdog: Watchdog,
+    heartbeat: Heartbeat,
+}
+
+impl Supervisor {
+    /// Starts `mode`.
+    pub fn new(mut mode: Box<Mode>, context: &Context) -> Supervisor {
+        mode.start();
+        Supervisor {
+            mode: mode,
+            context: context.clone(),
+            watchdog: Watchdog::new(&context.config.watchdog),
+            heartbeat: Heartbeat::new(&context.config.heartbeat),
         }
+    }
+
+    pub fn name(&self) -> String {
+        self.mode.name()
+    }
+
+    pub fn stop(&mut self) {
+        self.mode.stop();
+    }
 
-        // Handle mode change trigger
-        if is_mode_trigger(&control) {
-            next_mode(mode);
-            continue;
+    /// Returns false once the loop has to end, e.g. on the quit trigger.
+    pub fn handle(&mut self, message: Message) -> Result<bool> {
+        let mode = &mut self.mode;
+        let context = &self.context;
+        match message {
+            Message::Control(control) => {
+                let (controls, release) = self.watchdog.control(control);
+                if release {
+                    mode.release();
+                }
+                handle_controls(controls, mode, context)
+            }
+            Message::Event(event) => {
+                if self.heartbeat.event() {
+                    println!(":: Firmware back\r");
+                }
+                handle_event(event, mode, context)?;
+                Ok(true)
+            }
+            Message::Tick(dt) => {
+                if self.watchdog.tick(dt) {
+                    mode.release();
+                }
+                handle_beat(self.heartbeat.tick(dt), mode, context)?;
+                let paused = self.heartbeat.is_lost() && context.config.heartbeat.action == Action::Pause;
+                if !paused {
+                    mode.tick(dt)?;
+                }
+                Ok(true)
+            }
+            Message::Error(error) => Err(error),
+            Message::Quit => Ok(false),
         }
+    }
+
+    /// Enter the next mode, staying in the current one on error.
+    pub fn next_mode(&mut self) -> Result<()> {
+        next_mode(&mut self.mode)
+    }
+}
 
-        mode.handle(control);
+/// Decide whether the system can keep going after an error, possibly in a
+/// degraded way, or whether it has to be stopped.
+fn recover(error: Error) -> Result<()> {
+    match error {
+        // The faulty command or input is dropped, the following ones can still go through
+        Error::Encoding { .. } | Error::Decoding { .. } => {
+            println!(":: Error: {}\r", error);
+            Ok(())
+        }
+        // The keyboard keeps working without the gamepads
+        Error::Gamepad { .. } => {
+            println!(":: Error: {}\r", error);
+            Ok(())
+        }
+        // The operator is expected to home the machine or override
+        Error::NotReady { .. } => {
+            println!(":: {}\r", error);
+            Ok(())
+        }
+        // Losing a link to the machine means it can no longer be driven safely
+        Error::Io { .. } | Error::Disconnected { .. } => Err(error),
     }
-    return true;
 }
 
-fn next_mode(mode: &mut Box<Mode>) {
+/// Tell the firmware the host is alive, and react when the firmware is not.
+fn handle_beat(beat: Beat, mode: &mut Box<Mode>, context: &Context) -> Result<()> {
+    if beat.send {
+        context.driver.send(Command::Heartbeat)?;
+    }
+    if !beat.lost {
+        return Ok(());
+    }
+    let settings = &context.config.heartbeat;
+    println!(":: No news from the firmware for {} s\r", settings.timeout);
+    match settings.action {
+        Action::Warn => (),
+        Action::Pause => {
+            mode.release();
+            println!(":: Paused until the firmware is back\r");
+        }
+        Action::Stop => {
+            context.driver.send(Command::Shutdown)?;
+            context.machine.set(State::Faulted);
+        }
+    }
+    Ok(())
+}
+
+fn record(recorder: &Recorder, message: &Message) {
+    let entry = match *message {
+        Message::Control(ref control) => Entry::Control(control.clone()),
+        Message::Event(ref event) => Entry::Event(event.clone()),
+        Message::Tick(dt) => Entry::Tick(dt),
+        Message::Error(ref error) => Entry::Error(format!("{}", error)),
+        Message::Quit => return,
+    };
+    recorder.record(entry);
+}
+
+/// Returns false once the quit trigger is among the controls.
+fn handle_controls(controls: Vec<Control>, mode: &mut Box<Mode>, context: &Context) -> Result<bool> {
+    for control in controls {
+        if !handle_control(control, mode, context)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn handle_control(control: Control, mode: &mut Box<Mode>, context: &Context) -> Result<bool> {
+    // Handle quit trigger
+    if is_quit_trigger(&control) {
+        return Ok(false);
+    }
+
+    // Handle mode change trigger
+    if is_mode_trigger(&control) {
+        next_mode(mode)?;
+        return Ok(true);
+    }
+
+    if is_override_trigger(&control) {
+        context.machine.toggle_override();
+        return Ok(true);
+    }
+
+    mode.handle(control)?;
+    return Ok(true);
+}
+
+fn next_mode(mode: &mut Box<Mode>) -> Result<()> {
+    // The current mode goes on if the next one cannot be entered
+    let next = mode.next_mode()?;
     mode.stop();
-    *mode = mode.next_mode();
+    *mode = next;
     println!(":: Mode: {}\r", mode.name());
     mode.start();
+    Ok(())
 }
 
-fn handle_events(
-    events: &mpsc::Receiver<Event>,
-    _mode: &mut Box<Mode>,
-    _driver: &mpsc::Sender<Command>,
-) {
-    for event in events.try_iter() {
-        println!("{:?}\r", event)
-    }
+fn handle_event(event: Event, mode: &mut Box<Mode>, _context: &Context) -> Result<()> {
+    println!("{:?}\r", event);
+    mode.notify(&event)
 }
 
 fn is_mode_trigger(control: &Control) -> bool {
     match *control {
         Control::Joystick {
-            event:
-                gilrs::Event {
-                    id: _,
-                    event: gilrs::EventType::ButtonReleased { 0: button, 1: _ },
-                    time: _,
-                },
+            event: Joystick::ButtonReleased(button),
         } => return button == gilrs::Button::Mode,
         Control::Keyboard { keycode } => return keycode == 'm' as i32,
         _ => return false,
@@ -92,14 +329,17 @@ fn is_mode_trigger(control: &Control) -> bool {
 fn is_quit_trigger(control: &Control) -> bool {
     match *control {
         Control::Joystick {
-            event:
-                gilrs::Event {
-                    id: _,
-                    event: gilrs::EventType::ButtonReleased { 0: button, 1: _ },
-                    time: _,
-                },
+            event: Joystick::ButtonReleased(button),
         } => return button == gilrs::Button::Start,
         Control::Keyboard { keycode } => return keycode == 'q' as i32,
         _ => return false,
     }
 }
+
+/// Only on the keyboard, so that it cannot be pressed by accident.
+fn is_override_trigger(control: &Control) -> bool {
+    match *control {
+        Control::Keyboard { keycode } => return keycode == 'o' as i32,
+        _ => return false,
+    }
+}
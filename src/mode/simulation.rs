@@ -1,22 +1,25 @@
-use controller::control::Control;
+use controller::control::{Control, Joystick};
 use driver::command::Command;
+use error::Result;
 use gilrs;
 use gilrs::Button;
-use mode::manual::Manual;
-use mode::Mode;
-use std::sync::mpsc;
+use mode;
+use mode::{Context, Mode};
+use state::State;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub struct Simulation {
-    driver: mpsc::Sender<Command>,
+    context: Context,
 }
 
 impl Mode for Simulation {
-    fn init(driver: &mpsc::Sender<Command>) -> Self {
-        driver.send(Command::SetAbsolute).unwrap();
-        Simulation {
-            driver: driver.clone(),
-        }
+    fn init(context: &Context) -> Result<Self> {
+        context.machine.check(State::Calibrated, "Simulation mode")?;
+        context.driver.send(Command::SetAbsolute)?;
+        Ok(Simulation {
+            context: context.clone(),
+        })
     }
 
     fn start(&mut self) {}
@@ -27,33 +30,26 @@ impl Mode for Simulation {
         String::from("Simulation")
     }
 
-    fn next_mode(&self) -> Box<Mode> {
-        Box::new(Manual::init(&self.driver))
+    fn next_mode(&self) -> Result<Box<Mode>> {
+        mode::next(&self.name(), &self.context)
     }
 
-    fn handle(&mut self, control: Control) {
+    fn handle(&mut self, control: Control) -> Result<()> {
         match control {
-            Control::Joystick {
-                event:
-                    gilrs::Event {
-                        id: _,
-                        event,
-                        time: _,
-                    },
-            } => {
-                if let gilrs::EventType::ButtonReleased { 0: button, 1: _ } = event {
+            Control::Joystick { event } => {
+                if let Joystick::ButtonReleased(button) = event {
                     self.handle_button(button)
-                } else if let gilrs::EventType::AxisChanged {
-                    0: axis,
-                    1: value,
-                    2: _,
-                } = event
-                {
+                } else if let Joystick::AxisChanged(axis, value) = event {
                     self.handle_axis(axis, value)
                 }
             }
             Control::Keyboard { keycode } => self.handle_key(keycode),
         }
+        Ok(())
+    }
+
+    fn tick(&mut self, _dt: Duration) -> Result<()> {
+        Ok(())
     }
 }
 
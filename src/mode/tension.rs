@@ -0,0 +1,47 @@
+//! Balancing of the cable tensions, winding each cable in or out until it
+//! reaches the configured pre-tension.
+
+use config::Tension;
+use error::{Error, Result};
+use units::{Millimeters, Newtons};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Adjustment {
+    /// Change of length of each motor out of tolerance.
+    Move(Vec<(usize, Millimeters)>),
+    Balanced,
+    /// The cable of this motor is pulled harder than `overload`.
+    Overload(usize),
+}
+
+/// Next adjustment for the measured `tensions`, one for each of the `motors`.
+/// A cable is shortened to raise its tension, by at most `step` at a time.
+pub fn adjust(settings: &Tension, tensions: &[Newtons], motors: usize) -> Result<Adjustment> {
+    // A cable that was not measured cannot be taken as balanced
+    if tensions.len() != motors {
+        return Err(Error::Decoding {
+            context: format!("tension report of {} cables out of {}", tensions.len(), motors),
+        });
+    }
+    if let Some(motor) = tensions.iter().position(|&tension| tension > settings.overload) {
+        return Ok(Adjustment::Overload(motor));
+    }
+    let step = settings.step.0;
+    let moves: Vec<(usize, Millimeters)> = tensions
+        .iter()
+        .enumerate()
+        .filter_map(|(motor, &tension)| {
+            let missing = settings.target - tension;
+            if missing.0.abs() <= settings.tolerance.0 {
+                return None;
+            }
+            let change = -missing.0 * settings.compliance;
+            Some((motor, Millimeters(change.max(-step).min(step))))
+        })
+        .collect();
+    if moves.is_empty() {
+        Ok(Adjustment::Balanced)
+    } else {
+        Ok(Adjustment::Move(moves))
+    }
+}
@@ -0,0 +1,115 @@
+//! Dead-man watchdog, so that the machine does not keep moving at the last
+//! stick deflection when the operator or the gamepad is gone.
+
+use clock::seconds;
+use config;
+use gilrs;
+use controller::control::{Control, Joystick};
+use mode::{is_mode_trigger, is_quit_trigger};
+use std::time::Duration;
+
+#[derive(Debug)]
+pub struct Watchdog {
+    settings: config::Watchdog,
+    /// Time since the last control.
+    idle: Duration,
+    /// Whether the hold-to-move button is down.
+    held: bool,
+    /// Latest value of each stick and analog button, handed over once the
+    /// hold-to-move button is pressed.
+    sticks: Vec<Control>,
+}
+
+impl Watchdog {
+    pub fn new(settings: &config::Watchdog) -> Watchdog {
+        Watchdog {
+            settings: settings.clone(),
+            idle: Duration::from_secs(0),
+            held: false,
+            sticks: Vec::new(),
+        }
+    }
+
+    /// Controls to hand over to the mode, and whether it has to let go of the
+    /// sticks.
+    pub fn control(&mut self, control: Control) -> (Vec<Control>, bool) {
+        self.idle = Duration::from_secs(0);
+        if control == (Control::Joystick { event: Joystick::Disconnected }) {
+            self.held = false;
+            return (vec![control], true);
+        }
+        let hold = match self.settings.hold {
+            Some(hold) => hold,
+            None => return (vec![control], false),
+        };
+        let event = match control {
+            Control::Joystick { event } => event,
+            Control::Keyboard { .. } => return (self.gate(control, hold), false),
+        };
+        match event {
+            Joystick::ButtonPressed(button) if button == hold => {
+                self.held = true;
+                (self.sticks.clone(), false)
+            }
+            Joystick::ButtonReleased(button) if button == hold => {
+                self.held = false;
+                (vec![], true)
+            }
+            Joystick::ButtonChanged(button, _) if button == hold => (vec![], false),
+            Joystick::AxisChanged(..) | Joystick::ButtonChanged(..) => {
+                self.sticks.retain(|stick| !same_input(stick, &control));
+                self.sticks.push(control.clone());
+                if self.held {
+                    (vec![control], false)
+                } else {
+                    (vec![], false)
+                }
+            }
+            Joystick::ButtonReleased(_) => (self.gate(control, hold), false),
+            // Presses are only acted on once released
+            _ => (vec![control], false),
+        }
+    }
+
+    /// Let `control` through while the hold-to-move button is down, or when
+    /// it cannot move the machine.
+    fn gate(&self, control: Control, hold: gilrs::Button) -> Vec<Control> {
+        if self.held || is_harmless(&control) {
+            return vec![control];
+        }
+        println!(":: Hold {:?} to move\r", hold);
+        vec![]
+    }
+
+    /// Whether the mode has to let go of the sticks, no control having come
+    /// within the timeout. The hold-to-move button stands for the operator
+    /// when there is one.
+    pub fn tick(&mut self, dt: Duration) -> bool {
+        if self.settings.hold.is_some() || self.settings.timeout <= 0. {
+            return false;
+        }
+        let expired = seconds(self.idle) as f32 > self.settings.timeout;
+        self.idle += dt;
+        !expired && seconds(self.idle) as f32 > self.settings.timeout
+    }
+}
+
+/// Mode switch, quit and the list of waypoints.
+fn is_harmless(control: &Control) -> bool {
+    is_mode_trigger(control) || is_quit_trigger(control) || *control == (Control::Keyboard { keycode: 'p' as i32 })
+}
+
+/// Whether both controls come from the same stick or analog button.
+fn same_input(a: &Control, b: &Control) -> bool {
+    match (a, b) {
+        (
+            &Control::Joystick { event: Joystick::AxisChanged(a, _) },
+            &Control::Joystick { event: Joystick::AxisChanged(b, _) },
+        ) => a == b,
+        (
+            &Control::Joystick { event: Joystick::ButtonChanged(a, _) },
+            &Control::Joystick { event: Joystick::ButtonChanged(b, _) },
+        ) => a == b,
+        _ => false,
+    }
+}
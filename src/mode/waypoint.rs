@@ -0,0 +1,108 @@
+//! Poses taught in Manual mode, to come back to them or replay them as a
+//! G-code program.
+
+use clock::seconds;
+use config::Planner;
+use driver::command::{Command, GCode};
+use error::Result;
+use frame::{Attitude, Vector, World};
+use planner::plan_segment;
+use std::time::Duration;
+use units::{Degrees, DegreesPerMinute, Millimeters, MillimetersPerMinute};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Waypoint {
+    pub position: Vector<World, Millimeters>,
+    pub rotation: Attitude,
+}
+
+/// Poses sampled every `dt` from `from` to `to`, the translation and the
+/// rotation each following its own S-curve. The last pose is `to`.
+pub fn trajectory(
+    from: &Waypoint,
+    to: &Waypoint,
+    translational: MillimetersPerMinute,
+    rotational: DegreesPerMinute,
+    limits: &Planner,
+    dt: Duration,
+) -> Vec<Waypoint> {
+    let positions = plan_segment(
+        &[from.position.x.0, from.position.y.0, from.position.z.0],
+        &[to.position.x.0, to.position.y.0, to.position.z.0],
+        translational.per_second(),
+        &limits.translation,
+        dt,
+    );
+    let rotations = plan_segment(
+        &[from.rotation.u.0, from.rotation.v.0],
+        &[to.rotation.u.0, to.rotation.v.0],
+        rotational.per_second(),
+        &limits.rotation,
+        dt,
+    );
+
+    // The shorter of both motions holds its end while the other one finishes
+    let length = positions.len().max(rotations.len());
+    (0..length)
+        .map(|index| {
+            let p = &positions[index.min(positions.len() - 1)];
+            let r = &rotations[index.min(rotations.len() - 1)];
+            Waypoint {
+                position: Vector::new(Millimeters(p[0]), Millimeters(p[1]), Millimeters(p[2])),
+                rotation: Attitude {
+                    u: Degrees(r[0]),
+                    v: Degrees(r[1]),
+                },
+            }
+        })
+        .collect()
+}
+
+/// Pose reached `elapsed` into a trajectory sampled every `dt`, interpolated
+/// between the samples. The last pose is held past the end.
+pub fn at(trajectory: &[Waypoint], dt: Duration, elapsed: Duration) -> Waypoint {
+    let position = seconds(elapsed) / seconds(dt);
+    let index = position.floor() as usize;
+    if index + 1 >= trajectory.len() {
+        return trajectory[trajectory.len() - 1];
+    }
+    let ratio = (position - index as f64) as f32;
+    let (from, to) = (&trajectory[index], &trajectory[index + 1]);
+    Waypoint {
+        position: from.position + (to.position - from.position) * ratio,
+        rotation: Attitude {
+            u: from.rotation.u + (to.rotation.u - from.rotation.u) * ratio,
+            v: from.rotation.v + (to.rotation.v - from.rotation.v) * ratio,
+        },
+    }
+}
+
+/// Time taken by a trajectory sampled every `dt`.
+pub fn duration(trajectory: &[Waypoint], dt: Duration) -> Duration {
+    dt * trajectory.len().saturating_sub(1) as u32
+}
+
+/// G-code visiting every waypoint in turn, in absolute coordinates.
+pub fn program(
+    waypoints: &[Waypoint],
+    translational: MillimetersPerMinute,
+    rotational: DegreesPerMinute,
+    precision: usize,
+) -> Result<String> {
+    let mut commands = vec![Command::SetAbsolute];
+    for waypoint in waypoints.iter() {
+        commands.push(Command::move_to(waypoint.position.to_machine(), translational));
+        commands.push(Command::RotateTo {
+            u: Some(waypoint.rotation.u),
+            v: Some(waypoint.rotation.v),
+            f: Some(rotational),
+        });
+    }
+
+    let mut program = String::new();
+    for command in commands.iter() {
+        program.push_str(&command.to_gcode(precision)?);
+        program.push('\n');
+    }
+    Ok(program)
+}
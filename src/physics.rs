@@ -1,23 +1,27 @@
-use na::Vector3;
-
-type Num = f64;
+use clock::seconds;
+use frame::{Frame, Vector};
+use std::time::Duration;
+use units::{Millimeters, MillimetersPerMinute, Newtons};
 
 #[derive(Debug)]
-pub struct State {
-    pub position: Vector3<Num>,
-    pub velocity: Vector3<Num>,
+pub struct State<F: Frame> {
+    pub position: Vector<F, Millimeters>,
+    pub velocity: Vector<F, MillimetersPerMinute>,
 }
 
-impl State {
-    pub fn new() -> State {
+impl<F: Frame> State<F> {
+    pub fn new() -> State<F> {
         State {
-            position: Vector3::new(0., 0., 0.),
-            velocity: Vector3::new(0., 0., 0.),
+            position: Vector::new(Millimeters(0.), Millimeters(0.), Millimeters(0.)),
+            velocity: Vector::new(MillimetersPerMinute(0.), MillimetersPerMinute(0.), MillimetersPerMinute(0.)),
         }
     }
 }
 
-pub fn integrate(state: &mut State, force: &Vector3<Num>, mass: Num, dt: Num) {
-    state.position = state.position + state.velocity * dt;
-    state.velocity = state.velocity + force / mass * dt;
+/// Move `state` for `dt` under `force`, `mass` being in kg.
+pub fn integrate<F: Frame>(state: &mut State<F>, force: &Vector<F, Newtons>, mass: f32, dt: Duration) {
+    state.position += state.velocity.map(|velocity| velocity.over(dt));
+    // N/kg are m/s², that is 60 000 mm/min gained per second
+    let gain = seconds(dt) as f32 * 60_000. / mass;
+    state.velocity += force.map(|force| MillimetersPerMinute(force.0 * gain));
 }
@@ -0,0 +1,125 @@
+//! Motion planning: bound the acceleration and the jerk of every motion the
+//! host asks for, so that the cables are never jerked around.
+
+use clock::seconds;
+use std::time::Duration;
+
+// Distance to the end of a segment considered as arrived
+const TOLERANCE: f32 = 1e-3;
+// Give up on a segment that did not converge after this many steps
+const MAX_STEPS: usize = 100_000;
+
+/// Acceleration and jerk limits, in units per s² and per s³.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Limits {
+    pub acceleration: f32,
+    pub jerk: f32,
+}
+
+/// Jerk limited tracking of a velocity target along one axis, giving an
+/// S-curve velocity profile.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Profile {
+    pub velocity: f32,
+    pub acceleration: f32,
+}
+
+impl Profile {
+    pub fn new() -> Profile {
+        Profile {
+            velocity: 0.,
+            acceleration: 0.,
+        }
+    }
+
+    /// Move the velocity towards `target` for `dt`, returns the new velocity.
+    pub fn track(&mut self, target: f32, limits: &Limits, dt: Duration) -> f32 {
+        let dt = seconds(dt) as f32;
+        if dt <= 0. {
+            return self.velocity;
+        }
+        let step = limits.jerk * dt;
+        let error = target - self.velocity;
+
+        // Close enough to be reached in this step without breaking any limit
+        if error.abs() <= step.min(limits.acceleration) * dt && self.acceleration.abs() <= step {
+            self.velocity = target;
+            self.acceleration = 0.;
+            return self.velocity;
+        }
+
+        // Push harder as long as the acceleration can still be ramped down
+        // in time, hold it, or ramp it down
+        let direction = error.signum();
+        let raised = clamp(self.acceleration + direction * step, limits.acceleration);
+        let overshoot = |acceleration: f32| {
+            direction * (error - acceleration * dt - ramp(acceleration, limits.jerk)) < 0.
+        };
+        if !overshoot(raised) {
+            self.acceleration = raised;
+        } else if overshoot(self.acceleration) {
+            let lowered = self.acceleration.abs() - step;
+            self.acceleration = self.acceleration.signum() * lowered.max(0.);
+        }
+        self.velocity += self.acceleration * dt;
+
+        // Settle on the target rather than oscillate around it
+        if (target - self.velocity) * error < 0. {
+            self.velocity = target;
+            self.acceleration = 0.;
+        }
+        self.velocity
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.velocity == 0. && self.acceleration == 0.
+    }
+}
+
+/// Velocity still gained while bringing `acceleration` back to zero.
+fn ramp(acceleration: f32, jerk: f32) -> f32 {
+    acceleration * acceleration.abs() / (2. * jerk)
+}
+
+fn clamp(value: f32, limit: f32) -> f32 {
+    value.max(-limit).min(limit)
+}
+
+/// Sample a straight move from `from` to `to` every `dt`, following an
+/// S-curve on the travelled distance. `speed` is the cruise speed per second.
+/// The last point is always exactly `to`.
+pub fn plan_segment(
+    from: &[f32],
+    to: &[f32],
+    speed: f32,
+    limits: &Limits,
+    dt: Duration,
+) -> Vec<Vec<f32>> {
+    let delta: Vec<f32> = from.iter().zip(to.iter()).map(|(a, b)| b - a).collect();
+    let length = delta.iter().map(|d| d * d).sum::<f32>().sqrt();
+    let mut points = Vec::new();
+    if length == 0. || speed <= 0. {
+        points.push(to.to_vec());
+        return points;
+    }
+
+    let seconds = seconds(dt) as f32;
+    let lag = limits.acceleration / limits.jerk;
+    // Lowest speed kept until the end, so as not to crawl towards it forever
+    let creep = limits.acceleration.min(limits.jerk * seconds) * seconds;
+    let mut profile = Profile::new();
+    let mut travelled = 0.;
+    for _ in 0..MAX_STEPS {
+        // Brake early enough to also absorb the time the jerk limit takes
+        let remaining = length - travelled - profile.velocity * lag;
+        let braking = (2. * limits.acceleration * remaining.max(0.)).sqrt().max(creep);
+        travelled += profile.track(speed.min(braking), limits, dt) * seconds;
+        if travelled >= length - TOLERANCE {
+            break;
+        }
+        let ratio = travelled / length;
+        points.push(from.iter().zip(delta.iter()).map(|(a, d)| a + d * ratio).collect());
+    }
+    points.push(to.to_vec());
+    points
+}
@@ -0,0 +1,104 @@
+use config::Config;
+use controller::control::Control;
+use driver::command::Command;
+use error::{Context, Error, Result};
+use sensor::event::Event;
+use serde_json;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::mpsc;
+use std::thread;
+use clock::seconds;
+use std::time::{Duration, Instant};
+
+/// One line of a session file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    /// Seconds elapsed since the start of the session.
+    pub time: f64,
+    pub entry: Entry,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Entry {
+    /// Configuration in effect, recorded first so that the session can be
+    /// replayed with it.
+    Config(Config),
+    Control(Control),
+    Command(Command),
+    Event(Event),
+    Tick(Duration),
+    Error(String),
+}
+
+/// Handle timestamping entries and handing them over to the writer thread.
+/// A disabled recorder silently drops everything.
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    start: Instant,
+    tx: Option<mpsc::Sender<Record>>,
+}
+
+impl Recorder {
+    pub fn disabled() -> Recorder {
+        Recorder {
+            start: Instant::now(),
+            tx: None,
+        }
+    }
+
+    pub fn record(&self, entry: Entry) {
+        if let Some(ref tx) = self.tx {
+            tx.send(Record {
+                time: seconds(self.start.elapsed()),
+                entry: entry,
+            })
+            .ok();
+        }
+    }
+}
+
+pub struct Link {
+    done: mpsc::Receiver<()>,
+}
+
+impl Link {
+    /// Wait for the pending records to be written. Every `Recorder` must have
+    /// been dropped beforehand, otherwise this only returns after `timeout`.
+    pub fn close(self, timeout: Duration) -> bool {
+        self.done.recv_timeout(timeout).is_ok()
+    }
+}
+
+pub fn connect_recorder(path: &str) -> Result<(Recorder, Link)> {
+    let file = File::create(path).context("creating session file")?;
+    let (tx, rx) = mpsc::channel();
+    let (done_tx, done_rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        // Losing the recording must not stop the machine, so only report it
+        if let Err(error) = write(file, rx) {
+            println!(":: Recording stopped: {}\r", error);
+        }
+        done_tx.send(()).ok();
+    });
+
+    let recorder = Recorder {
+        start: Instant::now(),
+        tx: Some(tx),
+    };
+    return Ok((recorder, Link { done: done_rx }));
+}
+
+fn write(file: File, rx: mpsc::Receiver<Record>) -> Result<()> {
+    let mut writer = BufWriter::new(file);
+    for record in rx.iter() {
+        let line = serde_json::to_string(&record).map_err(|error| Error::Encoding {
+            context: format!("{}", error),
+        })?;
+        writeln!(writer, "{}", line).context("writing session file")?;
+        // Keep the file usable even if the program dies mid-session
+        writer.flush().context("writing session file")?;
+    }
+    Ok(())
+}
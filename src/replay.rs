@@ -0,0 +1,131 @@
+use config::Config;
+use driver::command::Command;
+use error::{Context, Error, Result};
+use message::Message;
+use mode;
+use mode::master_loop;
+use recorder::{Entry, Record, Recorder};
+use serde_json;
+use state::{Guard, State, Tracker};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Difference between the recorded and the regenerated command streams.
+#[derive(Debug, PartialEq)]
+pub struct Divergence {
+    pub index: usize,
+    pub expected: Option<Command>,
+    pub actual: Option<Command>,
+}
+
+pub fn load_session(path: &str) -> Result<Vec<Record>> {
+    let file = File::open(path).context("opening session file")?;
+    let mut records = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.context("reading session file")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str(&line).map_err(|error| Error::Decoding {
+            context: format!("line {} of {}: {}", number + 1, path, error),
+        })?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Feed the recorded controls, sensor events and ticks to `tx`, waiting
+/// between them as in the original session divided by `speed`. A `speed` of
+/// zero replays as fast as possible.
+pub fn connect_replay(records: Vec<Record>, speed: f64, tx: &mpsc::Sender<Message>) {
+    let tx = tx.clone();
+    thread::spawn(move || feed(records, speed, tx));
+}
+
+fn feed(records: Vec<Record>, speed: f64, tx: mpsc::Sender<Message>) {
+    let start = Instant::now();
+    for record in records {
+        let message = match record.entry {
+            Entry::Control(control) => Message::Control(control),
+            Entry::Event(event) => Message::Event(event),
+            Entry::Tick(dt) => Message::Tick(dt),
+            // Commands are the output being checked, errors cannot be rebuilt
+            Entry::Config(_) | Entry::Command(_) | Entry::Error(_) => continue,
+        };
+        if speed > 0. {
+            let due = Duration::from_millis((record.time / speed * 1000.) as u64);
+            let elapsed = start.elapsed();
+            if due > elapsed {
+                thread::sleep(due - elapsed);
+            }
+        }
+        if tx.send(message).is_err() {
+            break;
+        }
+    }
+}
+
+/// Run the session at `path` through the master loop and compare the
+/// regenerated commands with the recorded ones. The session is replayed with
+/// the configuration it was recorded with, unless `config` overrides it.
+pub fn replay(path: &str, speed: f64, config: Option<&Config>) -> Result<Vec<Divergence>> {
+    let records = load_session(path)?;
+    let recorded = records
+        .iter()
+        .filter_map(|record| match record.entry {
+            Entry::Config(ref config) => Some(config.clone()),
+            _ => None,
+        })
+        .next();
+    let config = config.cloned().or(recorded).ok_or_else(|| Error::Decoding {
+        context: format!("{}: no configuration recorded", path),
+    })?;
+    let expected: Vec<Command> = records
+        .iter()
+        .filter_map(|record| match record.entry {
+            Entry::Command(ref command) => Some(command.clone()),
+            _ => None,
+        })
+        .collect();
+
+    let (tx, rx) = mpsc::channel();
+    let (driver, commands) = mpsc::channel();
+    connect_replay(records, speed, &tx);
+    // The master loop ends once the whole session has been fed
+    drop(tx);
+    // As in main, the machine is left halted once the loop is over
+    let halt = driver.clone();
+    let machine = Tracker::new(State::Unhomed, !config.homing.required);
+    let context = mode::Context {
+        driver: Guard::new(driver, &machine),
+        config: config,
+        machine: machine,
+    };
+    let result = master_loop(rx, context, Recorder::disabled());
+    halt.send(Command::Shutdown).ok();
+    result?;
+
+    let actual: Vec<Command> = commands.try_iter().collect();
+    Ok(compare(&expected, &actual))
+}
+
+pub fn compare(expected: &[Command], actual: &[Command]) -> Vec<Divergence> {
+    let length = expected.len().max(actual.len());
+    (0..length)
+        .filter_map(|index| {
+            let expected = expected.get(index);
+            let actual = actual.get(index);
+            if expected == actual {
+                return None;
+            }
+            Some(Divergence {
+                index: index,
+                expected: expected.cloned(),
+                actual: actual.cloned(),
+            })
+        })
+        .collect()
+}
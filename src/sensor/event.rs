@@ -1,5 +1,97 @@
+use driver::command::Axis;
+use units::{Degrees, Millimeters, Newtons};
+
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Event {
     Content { string: String },
+    /// Position reported by the firmware, periodically or after `M114`.
+    Position {
+        x: Millimeters,
+        y: Millimeters,
+        z: Millimeters,
+        u: Option<Degrees>,
+        v: Option<Degrees>,
+        motors: Vec<Millimeters>,
+    },
+    /// Cable tensions measured by the load cells, one per motor.
+    Tension { tensions: Vec<Newtons> },
+    /// Axes that reached their home switch, after `G28`.
+    Homed { axes: Vec<Axis> },
+}
+
+impl Event {
+    /// Interpret a line received from the firmware, e.g.
+    /// `X:10.000 Y:0.000 Z:5.000 U:0.000 V:0.000 M0:120.000` or
+    /// `T0:20.500 T1:19.800` or `homed X Y Z`. Lines that are not understood
+    /// are kept as they are.
+    pub fn parse(line: &str) -> Event {
+        let event = homed(line)
+            .or_else(|| values(line).and_then(|values| position(&values).or_else(|| tension(&values))));
+        match event {
+            Some(event) => event,
+            None => Event::Content {
+                string: String::from(line),
+            },
+        }
+    }
+}
+
+fn homed(line: &str) -> Option<Event> {
+    let mut words = line.split_whitespace();
+    if words.next()? != "homed" {
+        return None;
+    }
+    let axes = words
+        .map(|word| {
+            let mut letters = word.chars();
+            match (letters.next(), letters.next()) {
+                (Some(letter), None) => Axis::from_letter(letter),
+                _ => None,
+            }
+        })
+        .collect::<Option<Vec<Axis>>>()?;
+    Some(Event::Homed { axes: axes })
+}
+
+fn values(line: &str) -> Option<Vec<(&str, f32)>> {
+    let mut values = Vec::new();
+    for word in line.split_whitespace() {
+        let mut parts = word.splitn(2, ':');
+        let name = parts.next()?;
+        let value: f32 = parts.next()?.parse().ok()?;
+        values.push((name, value));
+    }
+    Some(values)
+}
+
+fn find(values: &[(&str, f32)], name: &str) -> Option<f32> {
+    values.iter().find(|&&(n, _)| n == name).map(|&(_, v)| v)
+}
+
+fn position(values: &[(&str, f32)]) -> Option<Event> {
+    let value = |name: &str| find(values, name);
+    let mut motors = Vec::new();
+    while let Some(length) = value(&format!("M{}", motors.len())) {
+        motors.push(Millimeters(length));
+    }
+    Some(Event::Position {
+        x: Millimeters(value("X")?),
+        y: Millimeters(value("Y")?),
+        z: Millimeters(value("Z")?),
+        u: value("U").map(Degrees),
+        v: value("V").map(Degrees),
+        motors: motors,
+    })
+}
+
+fn tension(values: &[(&str, f32)]) -> Option<Event> {
+    let mut tensions = Vec::new();
+    while let Some(tension) = find(values, &format!("T{}", tensions.len())) {
+        tensions.push(Newtons(tension));
+    }
+    if tensions.is_empty() {
+        return None;
+    }
+    Some(Event::Tension { tensions: tensions })
 }
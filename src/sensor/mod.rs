@@ -1,38 +1,52 @@
+use config::Transport;
+use error::{Context, Result};
+use message::Message;
 use sensor::event::Event;
 use std::io::{BufRead, BufReader};
-use std::net::TcpStream;
 use std::sync::mpsc;
-use std::{thread, time};
+use std::thread;
+use transport;
+use transport::{Role, Stream};
 
 pub mod event;
 
-const WAIT_DURATION_MS: u64 = 1;
+pub struct Link {
+    stream: Box<Stream>,
+    thread: thread::JoinHandle<()>,
+}
 
-pub fn connect_sensor(address: &str) -> mpsc::Receiver<Event> {
-    let (tx, rx) = mpsc::channel();
+impl Link {
+    pub fn close(self) {
+        // Shutting the stream down unblocks the reading thread
+        self.stream.shutdown();
+        self.thread.join().ok();
+    }
+}
 
-    if let Some(stream) = TcpStream::connect(address).ok() {
-        thread::spawn(move || emit(stream, tx));
-    };
+pub fn connect_sensor(transport: &Transport, tx: &mpsc::Sender<Message>) -> Result<Link> {
+    let stream = transport::connect(transport, Role::Sensor)?;
+    let reader = stream.try_clone()?;
+    let tx = tx.clone();
 
-    return rx;
+    return Ok(Link {
+        stream: stream,
+        thread: thread::spawn(move || emit(reader, tx)),
+    });
 }
 
-fn emit(stream: TcpStream, tx: mpsc::Sender<Event>) {
-    let wait_duration = time::Duration::from_millis(WAIT_DURATION_MS);
-    let mut buf = BufReader::new(stream);
-    let mut line: String = String::new();
-    loop {
-        while let Ok(count) = buf.read_line(&mut line) {
-            if count == 0 {
+fn emit(stream: Box<Stream>, tx: mpsc::Sender<Message>) {
+    let buf = BufReader::new(stream);
+    for line in buf.lines() {
+        match line.context("reading from sensor") {
+            Ok(string) => {
+                if tx.send(Message::Event(Event::parse(&string))).is_err() {
+                    break;
+                }
+            }
+            Err(error) => {
+                tx.send(Message::Error(error)).ok();
                 break;
             }
-            tx.send(Event::Content {
-                string: line.clone(),
-            })
-            .unwrap();
-            line.clear();
         }
-        thread::sleep(wait_duration);
     }
 }
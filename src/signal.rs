@@ -0,0 +1,24 @@
+use error::{Context, Result};
+use message::Message;
+use signal_hook;
+use signal_hook::iterator::Signals;
+use std::sync::mpsc;
+use std::thread;
+
+/// Turn SIGINT and SIGTERM into a `Quit` message so that they go through the
+/// same orderly shutdown as the quit trigger.
+pub fn connect_signals(tx: &mpsc::Sender<Message>) -> Result<()> {
+    let signals = Signals::new(&[signal_hook::SIGINT, signal_hook::SIGTERM])
+        .context("registering signal handlers")?;
+    let tx = tx.clone();
+
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            if tx.send(Message::Quit).is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok(())
+}
@@ -1,22 +1,26 @@
+use clock::{seconds, Clock, SimulatedClock};
+use frame::{Vector, World};
 use physics;
 use physics::State;
+use units::Newtons;
 
-use na::Vector3;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub struct Lander {
-    pub state: State,
+    pub state: State<World>,
 }
 
 pub fn lander_test() {
     let mut lander = Lander {
         state: State::new(),
     };
-    let dt = 0.01;
-    let mut t = 0.;
-    while t <= 10. {
-        physics::integrate(&mut lander.state, &Vector3::new(10., 10., 0.), 1., dt);
-        t += dt;
-        println!("{:} {:?}\r", t, lander);
+    let clock = SimulatedClock::new();
+    let dt = Duration::from_millis(10);
+    let thrust = Vector::new(Newtons(10.), Newtons(10.), Newtons(0.));
+    while clock.now() <= Duration::from_secs(10) {
+        physics::integrate(&mut lander.state, &thrust, 1., dt);
+        clock.advance(dt);
+        println!("{:} {:?}\r", seconds(clock.now()), lander);
     }
 }
@@ -0,0 +1,143 @@
+//! What is known about the machine, deciding which modes can be entered and
+//! which commands can reach the driver.
+
+use driver::command::Command;
+use error::{Error, Result};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum State {
+    /// Position unknown, e.g. at startup.
+    Unhomed,
+    Homed,
+    /// Homed, and the cables were balanced.
+    Calibrated,
+    /// Something went wrong, the machine has to be homed again.
+    Faulted,
+}
+
+impl State {
+    /// Whether a machine in this state is ready for what needs `required`.
+    pub fn satisfies(self, required: State) -> bool {
+        level(self) >= level(required)
+    }
+}
+
+fn level(state: State) -> u8 {
+    match state {
+        State::Unhomed | State::Faulted => 0,
+        State::Homed => 1,
+        State::Calibrated => 2,
+    }
+}
+
+#[derive(Debug)]
+struct Inner {
+    state: State,
+    /// Set by the operator to move whatever the state.
+    overridden: bool,
+    /// Whether a dropped command was reported since the last change.
+    warned: bool,
+}
+
+/// State shared by the modes and the driver path.
+#[derive(Debug, Clone)]
+pub struct Tracker {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Tracker {
+    pub fn new(state: State, overridden: bool) -> Tracker {
+        Tracker {
+            inner: Arc::new(Mutex::new(Inner {
+                state: state,
+                overridden: overridden,
+                warned: false,
+            })),
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.inner.lock().unwrap().state
+    }
+
+    pub fn set(&self, state: State) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.state != state {
+            println!(":: Machine {:?}\r", state);
+        }
+        inner.state = state;
+        inner.warned = false;
+    }
+
+    pub fn is_overridden(&self) -> bool {
+        self.inner.lock().unwrap().overridden
+    }
+
+    /// Let everything through whatever the state, or stop doing so.
+    pub fn toggle_override(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.overridden = !inner.overridden;
+        inner.warned = false;
+        if inner.overridden {
+            println!(":: Override on, motion allowed while {:?}\r", inner.state);
+        } else {
+            println!(":: Override off\r");
+        }
+    }
+
+    /// Fails unless the machine is ready for `what`, which needs `required`.
+    pub fn check(&self, required: State, what: &str) -> Result<()> {
+        let inner = self.inner.lock().unwrap();
+        if inner.overridden || inner.state.satisfies(required) {
+            return Ok(());
+        }
+        Err(Error::NotReady {
+            context: format!("{} needs the machine {:?}, it is {:?}", what, required, inner.state),
+        })
+    }
+
+    /// Motion is only sent to a homed machine, except the homing itself.
+    pub fn permits(&self, command: &Command) -> bool {
+        if !command.is_motion() {
+            return true;
+        }
+        if let Command::MoveToHome { .. } = *command {
+            return true;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        if inner.overridden || inner.state.satisfies(State::Homed) {
+            return true;
+        }
+        if !inner.warned {
+            println!(":: Motion dropped, machine {:?}\r", inner.state);
+            inner.warned = true;
+        }
+        false
+    }
+}
+
+/// Sender of the driver commands, dropping those the machine is not ready
+/// for.
+#[derive(Debug, Clone)]
+pub struct Guard {
+    driver: mpsc::Sender<Command>,
+    machine: Tracker,
+}
+
+impl Guard {
+    pub fn new(driver: mpsc::Sender<Command>, machine: &Tracker) -> Guard {
+        Guard {
+            driver: driver,
+            machine: machine.clone(),
+        }
+    }
+
+    pub fn send(&self, command: Command) -> Result<()> {
+        if self.machine.permits(&command) {
+            self.driver.send(command)?;
+        }
+        Ok(())
+    }
+}
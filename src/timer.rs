@@ -0,0 +1,56 @@
+use clock::Clock;
+use message::Message;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub struct Link {
+    running: Arc<AtomicBool>,
+    thread: thread::JoinHandle<()>,
+}
+
+impl Link {
+    pub fn close(self) {
+        self.running.store(false, Ordering::SeqCst);
+        self.thread.join().ok();
+    }
+}
+
+/// Send a tick `frequency` times per second, carrying the time actually
+/// elapsed on `clock` since the previous one.
+pub fn connect_timer<C: Clock + 'static>(
+    frequency: f32,
+    clock: C,
+    tx: &mpsc::Sender<Message>,
+) -> Link {
+    let period = Duration::from_millis((1000.0 / frequency as f64) as u64);
+    let running = Arc::new(AtomicBool::new(true));
+    let tx = tx.clone();
+    let flag = running.clone();
+
+    return Link {
+        running: running,
+        thread: thread::spawn(move || tick(period, clock, tx, flag)),
+    };
+}
+
+fn tick<C: Clock>(period: Duration, clock: C, tx: mpsc::Sender<Message>, running: Arc<AtomicBool>) {
+    let mut deadline = Instant::now() + period;
+    let mut last = clock.now();
+    while running.load(Ordering::SeqCst) {
+        let now = Instant::now();
+        if deadline > now {
+            thread::sleep(deadline - now);
+        }
+        // Schedule from the previous deadline so that ticks do not drift
+        deadline += period;
+        // Sleeping is never exact, so report what really elapsed
+        let now = clock.now();
+        let dt = now - last;
+        last = now;
+        if tx.send(Message::Tick(dt)).is_err() {
+            break;
+        }
+    }
+}
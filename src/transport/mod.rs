@@ -0,0 +1,95 @@
+//! Byte streams carrying the G-code and the sensor lines, whatever reaches
+//! the firmware, or stands for it when scripting.
+
+use config::Transport;
+use error::{Context, Result};
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::os::unix::net::UnixStream;
+use transport::pipe::Pipe;
+
+pub mod pipe;
+pub mod serial;
+
+/// Connection to the firmware, read and written by different threads.
+pub trait Stream: Read + Write + Send {
+    /// Another handle on the same connection.
+    fn try_clone(&self) -> Result<Box<Stream>>;
+    /// End the connection, unblocking the threads still reading it.
+    fn shutdown(&self);
+}
+
+impl Stream for TcpStream {
+    fn try_clone(&self) -> Result<Box<Stream>> {
+        let stream = TcpStream::try_clone(self).context("cloning connection")?;
+        Ok(Box::new(stream))
+    }
+
+    fn shutdown(&self) {
+        TcpStream::shutdown(self, Shutdown::Both).ok();
+    }
+}
+
+impl Stream for UnixStream {
+    fn try_clone(&self) -> Result<Box<Stream>> {
+        let stream = UnixStream::try_clone(self).context("cloning connection")?;
+        Ok(Box::new(stream))
+    }
+
+    fn shutdown(&self) {
+        UnixStream::shutdown(self, Shutdown::Both).ok();
+    }
+}
+
+/// What a link is used for, deciding how it is opened.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Role {
+    /// G-code goes out, answers come back.
+    Driver,
+    /// Lines only come in.
+    Sensor,
+}
+
+/// Open the link described by `transport` for `role`.
+pub fn connect(transport: &Transport, role: Role) -> Result<Box<Stream>> {
+    let context = match role {
+        Role::Driver => "connecting to driver",
+        Role::Sensor => "connecting to sensor",
+    };
+    match *transport {
+        Transport::Tcp { ref address } => {
+            let stream = TcpStream::connect(address.as_str()).context(context)?;
+            stream.set_nodelay(true).context(context)?;
+            Ok(Box::new(stream))
+        }
+        Transport::Unix { ref path } => {
+            let stream = UnixStream::connect(path).context(context)?;
+            Ok(Box::new(stream))
+        }
+        Transport::Serial { ref path, baud } => {
+            let port = serial::open(path, baud).context(context)?;
+            Ok(Box::new(port))
+        }
+        Transport::File { ref path } => {
+            let pipe = match role {
+                Role::Driver => File::create(path).map(Pipe::writer),
+                Role::Sensor => File::open(path).map(Pipe::reader),
+            };
+            Ok(Box::new(pipe.context(context)?))
+        }
+        Transport::Stdout => match role {
+            Role::Driver => Ok(Box::new(Pipe::stdout().context(context)?)),
+            Role::Sensor => Err(one_way("stdout cannot be read")).context(context),
+        },
+        Transport::Stdin => match role {
+            Role::Driver => Err(one_way("stdin cannot be written")).context(context),
+            Role::Sensor => Ok(Box::new(Pipe::stdin().context(context)?)),
+        },
+    }
+}
+
+fn one_way(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
@@ -0,0 +1,142 @@
+//! Streams over a file descriptor: a serial port, a file or the standard
+//! input and output.
+
+use error::{Context, Result};
+use libc;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use transport::Stream;
+
+/// How long a read waits before checking whether the pipe was shut down.
+const POLL_PERIOD_MS: libc::c_int = 100;
+
+#[derive(Debug)]
+pub struct Pipe {
+    file: File,
+    readable: bool,
+    writable: bool,
+    /// Whether newlines need a carriage return, on the terminal held by
+    /// ncurses.
+    terminal: bool,
+    /// Shared by the clones, a descriptor cannot be shut down like a socket.
+    closed: Arc<AtomicBool>,
+}
+
+impl Pipe {
+    pub fn duplex(file: File) -> Pipe {
+        Pipe::new(file, true, true)
+    }
+
+    /// Nothing ever comes back, reads end at once.
+    pub fn writer(file: File) -> Pipe {
+        Pipe::new(file, false, true)
+    }
+
+    pub fn reader(file: File) -> Pipe {
+        Pipe::new(file, true, false)
+    }
+
+    pub fn stdin() -> io::Result<Pipe> {
+        Ok(Pipe::reader(duplicate(libc::STDIN_FILENO)?))
+    }
+
+    pub fn stdout() -> io::Result<Pipe> {
+        let mut pipe = Pipe::writer(duplicate(libc::STDOUT_FILENO)?);
+        pipe.terminal = unsafe { libc::isatty(libc::STDOUT_FILENO) } == 1;
+        Ok(pipe)
+    }
+
+    fn new(file: File, readable: bool, writable: bool) -> Pipe {
+        Pipe {
+            file: file,
+            readable: readable,
+            writable: writable,
+            terminal: false,
+            closed: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+/// Own a copy of `fd`, so that dropping the pipe leaves the original open.
+fn duplicate(fd: RawFd) -> io::Result<File> {
+    let copy = unsafe { libc::dup(fd) };
+    if copy < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { File::from_raw_fd(copy) })
+}
+
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.readable {
+            return Ok(0);
+        }
+        loop {
+            if self.closed.load(Ordering::SeqCst) {
+                return Ok(0);
+            }
+            let mut poll = libc::pollfd {
+                fd: self.file.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            match unsafe { libc::poll(&mut poll, 1, POLL_PERIOD_MS) } {
+                0 => continue,
+                ready if ready > 0 => return self.file.read(buf),
+                _ => {
+                    let error = io::Error::last_os_error();
+                    if error.kind() != io::ErrorKind::Interrupted {
+                        return Err(error);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.writable {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "read-only link"));
+        }
+        if !self.terminal {
+            return self.file.write(buf);
+        }
+        for (index, line) in buf.split(|&byte| byte == b'\n').enumerate() {
+            if index > 0 {
+                self.file.write_all(b"\r\n")?;
+            }
+            self.file.write_all(line)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Stream for Pipe {
+    fn try_clone(&self) -> Result<Box<Stream>> {
+        let file = self.file.try_clone().context("cloning link")?;
+        Ok(Box::new(Pipe {
+            file: file,
+            readable: self.readable,
+            writable: self.writable,
+            terminal: self.terminal,
+            closed: self.closed.clone(),
+        }))
+    }
+
+    fn shutdown(&self) {
+        if self.writable && unsafe { libc::isatty(self.file.as_raw_fd()) } == 1 {
+            // Let the queued G-code out of a serial port before it is dropped
+            unsafe { libc::tcdrain(self.file.as_raw_fd()) };
+        }
+        self.closed.store(true, Ordering::SeqCst);
+    }
+}
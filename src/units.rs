@@ -0,0 +1,127 @@
+//! Physical quantities, so that a length cannot be given where an angle or a
+//! feed rate is expected.
+
+use clock::seconds;
+use std::fmt;
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
+use std::time::Duration;
+
+/// Any quantity, to work on the bare number when the unit does not matter.
+pub trait Quantity: Copy {
+    fn value(self) -> f32;
+    fn from_value(value: f32) -> Self;
+}
+
+/// Unitless, e.g. a stick deflection.
+impl Quantity for f32 {
+    fn value(self) -> f32 {
+        self
+    }
+
+    fn from_value(value: f32) -> f32 {
+        value
+    }
+}
+
+macro_rules! quantity {
+    ($name:ident, $symbol:expr) => {
+        #[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
+        pub struct $name(pub f32);
+
+        impl Quantity for $name {
+            fn value(self) -> f32 {
+                self.0
+            }
+
+            fn from_value(value: f32) -> $name {
+                $name(value)
+            }
+        }
+
+        impl Add for $name {
+            type Output = $name;
+            fn add(self, other: $name) -> $name {
+                $name(self.0 + other.0)
+            }
+        }
+
+        impl AddAssign for $name {
+            fn add_assign(&mut self, other: $name) {
+                self.0 += other.0;
+            }
+        }
+
+        impl Sub for $name {
+            type Output = $name;
+            fn sub(self, other: $name) -> $name {
+                $name(self.0 - other.0)
+            }
+        }
+
+        impl SubAssign for $name {
+            fn sub_assign(&mut self, other: $name) {
+                self.0 -= other.0;
+            }
+        }
+
+        impl Neg for $name {
+            type Output = $name;
+            fn neg(self) -> $name {
+                $name(-self.0)
+            }
+        }
+
+        impl Mul<f32> for $name {
+            type Output = $name;
+            fn mul(self, factor: f32) -> $name {
+                $name(self.0 * factor)
+            }
+        }
+
+        impl Div<f32> for $name {
+            type Output = $name;
+            fn div(self, divisor: f32) -> $name {
+                $name(self.0 / divisor)
+            }
+        }
+
+        /// Ratio between two quantities of the same kind.
+        impl Div for $name {
+            type Output = f32;
+            fn div(self, other: $name) -> f32 {
+                self.0 / other.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{} {}", self.0, $symbol)
+            }
+        }
+    };
+}
+
+macro_rules! rate {
+    ($name:ident, $quantity:ident) => {
+        impl $name {
+            /// Distance covered at this rate during `dt`.
+            pub fn over(self, dt: Duration) -> $quantity {
+                $quantity(self.0 * seconds(dt) as f32 / 60.)
+            }
+
+            /// Same rate, per second rather than per minute.
+            pub fn per_second(self) -> f32 {
+                self.0 / 60.
+            }
+        }
+    };
+}
+
+quantity!(Millimeters, "mm");
+quantity!(Degrees, "deg");
+quantity!(MillimetersPerMinute, "mm/min");
+quantity!(DegreesPerMinute, "deg/min");
+quantity!(Newtons, "N");
+
+rate!(MillimetersPerMinute, Millimeters);
+rate!(DegreesPerMinute, Degrees);
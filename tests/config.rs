@@ -0,0 +1,41 @@
+extern crate fssp_simulator;
+extern crate gilrs;
+
+use fssp_simulator::config::Config;
+use fssp_simulator::error::Error;
+use fssp_simulator::planner::Limits;
+use gilrs::Button;
+
+#[test]
+fn configuration_keeps_the_defaults_of_missing_sections() {
+    let content = "[planner.rotation]\nacceleration = 100.0\njerk = 1000.0\n";
+    let config = Config::parse(content).unwrap();
+    assert_eq!(config.planner.rotation, Limits { acceleration: 100., jerk: 1_000. });
+    assert_eq!(config.planner.translation, Config::default().planner.translation);
+}
+
+#[test]
+fn planner_limits_must_be_positive() {
+    for content in [
+        "[planner.translation]\nacceleration = 500.0\njerk = 0.0\n",
+        "[planner.rotation]\nacceleration = 0.0\njerk = 1000.0\n",
+        "[planner.rotation]\nacceleration = -1.0\njerk = 1000.0\n",
+    ]
+    .iter()
+    {
+        match Config::parse(content) {
+            Err(Error::Decoding { .. }) => (),
+            other => panic!("{:?} accepted: {:?}", content, other),
+        }
+    }
+}
+
+#[test]
+fn hold_button_must_be_free_in_every_mode() {
+    match Config::parse("[watchdog]\nhold = \"LeftTrigger2\"\n") {
+        Err(Error::Decoding { .. }) => (),
+        other => panic!("accepted: {:?}", other),
+    }
+    let config = Config::parse("[watchdog]\nhold = \"LeftThumb\"\n").unwrap();
+    assert_eq!(config.watchdog.hold, Some(Button::LeftThumb));
+}
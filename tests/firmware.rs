@@ -0,0 +1,403 @@
+extern crate fssp_simulator;
+
+use fssp_simulator::config::Transport;
+use fssp_simulator::driver::command::{Axis, Command, GCode, DEFAULT_PRECISION};
+use fssp_simulator::driver::connect_driver;
+use fssp_simulator::driver::filter::Filter;
+use fssp_simulator::firmware::gcode;
+use fssp_simulator::firmware::machine::Machine;
+use fssp_simulator::firmware::Firmware;
+use fssp_simulator::message::Message;
+use fssp_simulator::recorder::Recorder;
+use fssp_simulator::sensor::connect_sensor;
+use fssp_simulator::sensor::event::Event;
+use fssp_simulator::units::{Degrees, DegreesPerMinute, Millimeters, MillimetersPerMinute, Newtons};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn tcp(address: &str) -> Transport {
+    Transport::Tcp {
+        address: String::from(address),
+    }
+}
+
+fn wait_until<F: Fn() -> bool>(condition: F, timeout: Duration) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        if condition() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    condition()
+}
+
+#[test]
+fn gcode_round_trips_through_the_parser() {
+    let commands = vec![
+        Command::MoveTo { x: Some(Millimeters(1350.)), y: Some(Millimeters(-20.)), z: None, f: Some(MillimetersPerMinute(6000.)) },
+        Command::MoveTo { x: None, y: None, z: Some(Millimeters(4.)), f: None },
+        Command::MoveTo { x: Some(Millimeters(0.125)), y: Some(Millimeters(-1350.5)), z: None, f: Some(MillimetersPerMinute(6000.)) },
+        Command::RotateTo { u: Some(Degrees(10.)), v: Some(Degrees(-5.)), f: Some(DegreesPerMinute(2000.)) },
+        Command::MoveMotorTo { m: 2, l: Millimeters(150.), f: None },
+        Command::ArcTo {
+            clockwise: true,
+            x: Some(Millimeters(10.)),
+            y: Some(Millimeters(0.)),
+            z: None,
+            i: Millimeters(5.),
+            j: Millimeters(0.),
+            f: Some(MillimetersPerMinute(1200.)),
+        },
+        Command::ArcTo {
+            clockwise: false,
+            x: None,
+            y: Some(Millimeters(3.)),
+            z: Some(Millimeters(1.)),
+            i: Millimeters(0.),
+            j: Millimeters(-1.5),
+            f: None,
+        },
+        Command::MoveToHome { axes: vec![] },
+        Command::MoveToHome { axes: vec![Axis::X, Axis::V] },
+        Command::SetFeedOverride { s: 50. },
+        Command::EnableMotors,
+        Command::DisableMotors,
+        Command::QueryPosition,
+        Command::Heartbeat,
+        Command::Pause { p: Some(500.), s: Some(1.) },
+        Command::SetAbsolute,
+        Command::SetRelative,
+        Command::SetAttachPosition { n: 2, x: Millimeters(1.), y: Millimeters(2.), z: Millimeters(3.) },
+        Command::SetPosition { x: Some(Millimeters(0.)), y: None, z: Some(Millimeters(7.)) },
+        Command::SetMotorZero { m: 3 },
+        Command::Shutdown,
+    ];
+    for command in commands {
+        let code = command.to_gcode(DEFAULT_PRECISION).unwrap();
+        assert_eq!(gcode::parse(&code).unwrap(), command, "parsing `{}`", code);
+    }
+}
+
+#[test]
+fn gcode_keeps_the_configured_precision() {
+    let command = Command::MoveTo { x: Some(Millimeters(1350.123_4)), y: Some(Millimeters(-0.000_1)), z: Some(Millimeters(2.75)), f: Some(MillimetersPerMinute(6000.)) };
+    assert_eq!(command.to_gcode(3).unwrap(), "G1 X1350.123 Y0 Z2.75 F6000");
+    assert_eq!(command.to_gcode(1).unwrap(), "G1 X1350.1 Y0 Z2.8 F6000");
+    assert_eq!(command.to_gcode(0).unwrap(), "G1 X1350 Y0 Z3 F6000");
+}
+
+#[test]
+fn driver_link_moves_the_simulated_machine() {
+    let firmware = Firmware::bind("127.0.0.1:0", "127.0.0.1:0").unwrap();
+    let address = firmware.driver_address().unwrap().to_string();
+    let machine = firmware.start();
+
+    let (tx, _rx) = mpsc::channel();
+    let (driver, link) = connect_driver(&tcp(&address), DEFAULT_PRECISION, &tx, &Recorder::disabled()).unwrap();
+    driver.send(Command::MoveTo { x: Some(Millimeters(100.)), y: Some(Millimeters(-50.)), z: None, f: Some(MillimetersPerMinute(30_000.)) }).unwrap();
+    driver.send(Command::MoveMotorTo { m: 1, l: Millimeters(20.), f: None }).unwrap();
+
+    let arrived = wait_until(
+        || {
+            let machine = machine.lock().unwrap();
+            machine.x.position == 100. && machine.y.position == -50. && machine.motors[1].position == 20.
+        },
+        Duration::from_secs(5),
+    );
+    assert!(arrived, "{:?}", machine.lock().unwrap());
+
+    drop(driver);
+    assert!(link.close(Duration::from_secs(1)));
+}
+
+#[test]
+fn motion_respects_the_acceleration_limit() {
+    let firmware = Firmware::bind("127.0.0.1:0", "127.0.0.1:0").unwrap();
+    let address = firmware.driver_address().unwrap().to_string();
+    let machine = firmware.start();
+
+    let (tx, _rx) = mpsc::channel();
+    let (driver, _link) = connect_driver(&tcp(&address), DEFAULT_PRECISION, &tx, &Recorder::disabled()).unwrap();
+    driver.send(Command::MoveTo { x: Some(Millimeters(1000.)), y: None, z: None, f: None }).unwrap();
+    thread::sleep(Duration::from_millis(100));
+
+    // A rapid move cannot get anywhere near its target in 100 ms
+    let machine = machine.lock().unwrap();
+    assert!(machine.x.position > 0. && machine.x.position < 10., "{:?}", machine.x);
+}
+
+#[test]
+fn sensor_link_streams_the_machine_status() {
+    let firmware = Firmware::bind("127.0.0.1:0", "127.0.0.1:0").unwrap();
+    let address = firmware.sensor_address().unwrap().to_string();
+    firmware.start();
+
+    let (tx, rx) = mpsc::channel();
+    let link = connect_sensor(&tcp(&address), &tx).unwrap();
+    match rx.recv_timeout(Duration::from_secs(2)) {
+        Ok(Message::Event(Event::Position { x, motors, .. })) => {
+            assert_eq!(x, Millimeters(0.));
+            assert_eq!(motors.len(), 4);
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+    link.close();
+}
+
+#[test]
+fn position_query_is_answered_on_the_driver_link() {
+    let firmware = Firmware::bind("127.0.0.1:0", "127.0.0.1:0").unwrap();
+    let address = firmware.driver_address().unwrap().to_string();
+    let machine = firmware.start();
+    machine.lock().unwrap().execute(&Command::SetPosition {
+        x: Some(Millimeters(12.5)),
+        y: None,
+        z: None,
+    });
+
+    let (tx, rx) = mpsc::channel();
+    let (driver, _link) = connect_driver(&tcp(&address), DEFAULT_PRECISION, &tx, &Recorder::disabled()).unwrap();
+    driver.send(Command::QueryPosition).unwrap();
+    match rx.recv_timeout(Duration::from_secs(2)) {
+        Ok(Message::Event(Event::Position { x, y, u, .. })) => {
+            assert_eq!(x, Millimeters(12.5));
+            assert_eq!(y, Millimeters(0.));
+            assert_eq!(u, Some(Degrees(0.)));
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn homing_is_reported_on_the_driver_link() {
+    let firmware = Firmware::bind("127.0.0.1:0", "127.0.0.1:0").unwrap();
+    let address = firmware.driver_address().unwrap().to_string();
+    let machine = firmware.start();
+    machine.lock().unwrap().execute(&Command::SetPosition {
+        x: Some(Millimeters(20.)),
+        y: None,
+        z: None,
+    });
+
+    let (tx, rx) = mpsc::channel();
+    let (driver, _link) = connect_driver(&tcp(&address), DEFAULT_PRECISION, &tx, &Recorder::disabled()).unwrap();
+    driver.send(Command::MoveToHome { axes: vec![Axis::X, Axis::Z] }).unwrap();
+    match rx.recv_timeout(Duration::from_secs(2)) {
+        Ok(Message::Event(event)) => assert_eq!(event, Event::Homed { axes: vec![Axis::X, Axis::Z] }),
+        other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(machine.lock().unwrap().x.position, 0.);
+}
+
+#[test]
+fn homing_outlasts_the_host_timeout() {
+    let firmware = Firmware::bind("127.0.0.1:0", "127.0.0.1:0").unwrap();
+    let address = firmware.driver_address().unwrap().to_string();
+    let machine = firmware.start();
+    machine.lock().unwrap().execute(&Command::SetPosition {
+        x: Some(Millimeters(1500.)),
+        y: None,
+        z: None,
+    });
+
+    // The heartbeats sent meanwhile are only read once the home is over
+    let (tx, rx) = mpsc::channel();
+    let (driver, _link) = connect_driver(&tcp(&address), DEFAULT_PRECISION, &tx, &Recorder::disabled()).unwrap();
+    driver.send(Command::Heartbeat).unwrap();
+    driver.send(Command::MoveToHome { axes: vec![Axis::X] }).unwrap();
+    for _ in 0..8 {
+        thread::sleep(Duration::from_millis(500));
+        driver.send(Command::Heartbeat).unwrap();
+    }
+    match rx.recv_timeout(Duration::from_secs(2)) {
+        Ok(Message::Event(event)) => assert_eq!(event, Event::Homed { axes: vec![Axis::X] }),
+        other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(machine.lock().unwrap().x.position, 0.);
+}
+
+#[test]
+fn homing_short_of_home_is_an_error() {
+    let firmware = Firmware::bind("127.0.0.1:0", "127.0.0.1:0").unwrap();
+    let address = firmware.driver_address().unwrap().to_string();
+    let machine = firmware.start();
+    machine.lock().unwrap().execute(&Command::SetPosition {
+        x: Some(Millimeters(20.)),
+        y: None,
+        z: None,
+    });
+
+    let (tx, rx) = mpsc::channel();
+    let (driver, _link) = connect_driver(&tcp(&address), DEFAULT_PRECISION, &tx, &Recorder::disabled()).unwrap();
+    driver.send(Command::DisableMotors).unwrap();
+    driver.send(Command::MoveToHome { axes: vec![Axis::X] }).unwrap();
+    match rx.recv_timeout(Duration::from_secs(2)) {
+        Ok(Message::Event(event)) => assert_eq!(event, Event::Content { string: String::from("error: homing of X halted") }),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn unknown_lines_are_kept_as_content() {
+    assert_eq!(
+        Event::parse("ok"),
+        Event::Content { string: String::from("ok") }
+    );
+    assert_eq!(
+        Event::parse("X:1.00 Y:2.00 Z:3.00 E:0.00"),
+        Event::Position {
+            x: Millimeters(1.),
+            y: Millimeters(2.),
+            z: Millimeters(3.),
+            u: None,
+            v: None,
+            motors: vec![],
+        }
+    );
+    assert_eq!(
+        Event::parse("T0:20.50 T1:19.00"),
+        Event::Tension { tensions: vec![Newtons(20.5), Newtons(19.)] }
+    );
+}
+
+#[test]
+fn machine_homes_single_axes_and_scales_the_feed() {
+    let mut machine = Machine::new();
+    machine.execute(&Command::SetPosition { x: Some(Millimeters(10.)), y: Some(Millimeters(20.)), z: None });
+    machine.execute(&Command::MoveToHome { axes: vec![Axis::X] });
+    assert_eq!(machine.x.target, 0.);
+    assert_eq!(machine.y.target, 20.);
+
+    machine.execute(&Command::SetFeedOverride { s: 50. });
+    machine.execute(&Command::MoveTo { x: None, y: Some(Millimeters(0.)), z: None, f: Some(MillimetersPerMinute(1000.)) });
+    assert_eq!(machine.y.feed, 500.);
+
+    machine.execute(&Command::DisableMotors);
+    machine.execute(&Command::MoveTo { x: None, y: Some(Millimeters(5.)), z: None, f: None });
+    assert_eq!(machine.y.target, machine.y.position);
+    machine.execute(&Command::EnableMotors);
+    machine.execute(&Command::MoveTo { x: None, y: Some(Millimeters(5.)), z: None, f: None });
+    assert_eq!(machine.y.target, 5.);
+}
+
+#[test]
+fn machine_halts_when_the_host_goes_quiet() {
+    let mut machine = Machine::new();
+    machine.execute(&Command::MoveTo { x: Some(Millimeters(10_000.)), y: None, z: None, f: None });
+    // Without any heartbeat so far, nothing is expected from the host
+    for _ in 0..300 {
+        machine.step(0.01);
+    }
+    assert!(machine.x.is_moving());
+
+    machine.execute(&Command::Heartbeat);
+    for _ in 0..150 {
+        machine.step(0.01);
+    }
+    machine.execute(&Command::Heartbeat);
+    for _ in 0..150 {
+        machine.step(0.01);
+    }
+    assert!(machine.x.is_moving());
+    for _ in 0..100 {
+        machine.step(0.01);
+    }
+    assert!(!machine.x.is_moving());
+}
+
+#[test]
+fn driver_filter_drops_repeated_targets_and_merges_bursts() {
+    let move_to = |x: f32| Command::MoveTo { x: Some(Millimeters(x)), y: Some(Millimeters(0.)), z: None, f: None };
+    let turn_to = |u: f32| Command::RotateTo { u: Some(Degrees(u)), v: None, f: None };
+    let mut filter = Filter::new();
+
+    // Only the mode the firmware is known to be in allows dropping anything
+    assert_eq!(filter.batch(vec![move_to(1.), move_to(1.)]), vec![move_to(1.), move_to(1.)]);
+    assert_eq!(filter.batch(vec![Command::SetAbsolute]), vec![Command::SetAbsolute]);
+    assert_eq!(filter.batch(vec![move_to(1.)]), vec![move_to(1.)]);
+    assert_eq!(filter.batch(vec![move_to(1.), turn_to(5.)]), vec![turn_to(5.)]);
+    assert_eq!(filter.batch(vec![Command::Heartbeat, move_to(1.)]), vec![Command::Heartbeat]);
+
+    // Only the latest target of each slot is kept, without crossing other commands
+    let burst = vec![move_to(2.), turn_to(6.), move_to(3.), Command::Pause { p: Some(10.), s: None }, move_to(4.), move_to(5.)];
+    assert_eq!(
+        filter.batch(burst),
+        vec![turn_to(6.), move_to(3.), Command::Pause { p: Some(10.), s: None }, move_to(5.)]
+    );
+    // Anything else may have moved the machine, repeating a target is meaningful again
+    assert_eq!(filter.batch(vec![move_to(5.)]), vec![]);
+    assert_eq!(filter.batch(vec![Command::DisableMotors, move_to(5.)]), vec![Command::DisableMotors, move_to(5.)]);
+
+    // A partial target does not replace a complete one
+    let partial = Command::MoveTo { x: Some(Millimeters(6.)), y: None, z: None, f: None };
+    assert_eq!(filter.batch(vec![move_to(7.), partial.clone()]), vec![move_to(7.), partial]);
+
+    // Relative moves add up, none of them can go
+    filter.batch(vec![Command::SetRelative]);
+    assert_eq!(filter.batch(vec![move_to(1.), move_to(1.)]), vec![move_to(1.), move_to(1.)]);
+
+    let statistics = filter.statistics();
+    assert_eq!(statistics.suppressed, 3);
+    assert_eq!(statistics.coalesced, 2);
+    assert_eq!(statistics.sent, 17);
+}
+
+#[test]
+fn driver_link_counts_the_suppressed_commands() {
+    let firmware = Firmware::bind("127.0.0.1:0", "127.0.0.1:0").unwrap();
+    let address = firmware.driver_address().unwrap().to_string();
+    let machine = firmware.start();
+
+    let (tx, _rx) = mpsc::channel();
+    let (driver, link) = connect_driver(&tcp(&address), DEFAULT_PRECISION, &tx, &Recorder::disabled()).unwrap();
+    driver.send(Command::SetAbsolute).unwrap();
+    for _ in 0..10 {
+        driver.send(Command::MoveMotorTo { m: 0, l: Millimeters(15.), f: None }).unwrap();
+    }
+
+    let arrived = wait_until(|| machine.lock().unwrap().motors[0].position == 15., Duration::from_secs(5));
+    assert!(arrived, "{:?}", machine.lock().unwrap());
+
+    drop(driver);
+    assert!(link.close(Duration::from_secs(1)));
+    let statistics = link.statistics();
+    assert_eq!(statistics.sent, 2);
+    assert_eq!(statistics.suppressed + statistics.coalesced, 9);
+}
+
+#[test]
+fn driver_link_repeats_a_target_once_the_firmware_moved_by_itself() {
+    let firmware = Firmware::bind("127.0.0.1:0", "127.0.0.1:0").unwrap();
+    let address = firmware.driver_address().unwrap().to_string();
+    let machine = firmware.start();
+    let target = Command::MoveTo { x: Some(Millimeters(10.)), y: None, z: None, f: None };
+
+    let (tx, rx) = mpsc::channel();
+    let (driver, _link) = connect_driver(&tcp(&address), DEFAULT_PRECISION, &tx, &Recorder::disabled()).unwrap();
+    driver.send(Command::SetAbsolute).unwrap();
+    driver.send(target.clone()).unwrap();
+    assert!(wait_until(|| machine.lock().unwrap().x.position == 10., Duration::from_secs(5)));
+
+    // Halted away from the target without the host knowing, until it asks
+    machine.lock().unwrap().execute(&Command::SetPosition { x: Some(Millimeters(0.)), y: None, z: None });
+    driver.send(Command::QueryPosition).unwrap();
+    match rx.recv_timeout(Duration::from_secs(2)) {
+        Ok(Message::Event(Event::Position { x, .. })) => assert_eq!(x, Millimeters(0.)),
+        other => panic!("unexpected {:?}", other),
+    }
+    driver.send(target).unwrap();
+    assert!(wait_until(|| machine.lock().unwrap().x.position == 10., Duration::from_secs(5)));
+}
+
+#[test]
+fn driver_filter_forgets_the_targets_on_a_halt() {
+    let move_to = |x: f32| Command::MoveTo { x: Some(Millimeters(x)), y: Some(Millimeters(0.)), z: None, f: None };
+    let mut filter = Filter::new();
+    filter.batch(vec![Command::SetAbsolute, move_to(1.)]);
+    assert_eq!(filter.batch(vec![Command::Shutdown, move_to(1.)]), vec![Command::Shutdown, move_to(1.)]);
+    assert_eq!(filter.batch(vec![move_to(1.)]), vec![]);
+    filter.forget();
+    assert_eq!(filter.batch(vec![move_to(1.)]), vec![move_to(1.)]);
+}
@@ -0,0 +1,911 @@
+extern crate fssp_simulator;
+extern crate gilrs;
+
+use fssp_simulator::clock::seconds;
+use fssp_simulator::config::{Action, Config};
+use fssp_simulator::controller::control::{Control, Joystick};
+use fssp_simulator::driver::command::{Axis as CommandAxis, Command};
+use fssp_simulator::message::Message;
+use fssp_simulator::mode::calibration::Calibration;
+use fssp_simulator::mode::harness::Harness;
+use fssp_simulator::mode::heartbeat::{Beat, Heartbeat};
+use fssp_simulator::mode::homing::Homing;
+use fssp_simulator::mode::manual::Manual;
+use fssp_simulator::mode::simulation::Simulation;
+use fssp_simulator::mode::{master_loop, Context};
+use fssp_simulator::planner::Limits;
+use fssp_simulator::recorder::Recorder;
+use fssp_simulator::sensor::event::Event;
+use fssp_simulator::state::{Guard, State, Tracker};
+use fssp_simulator::units::{Degrees, DegreesPerMinute, Millimeters, MillimetersPerMinute, Newtons};
+use gilrs::{Axis, Button};
+use std::sync::mpsc;
+use std::time::Duration;
+
+fn ms(millis: u64) -> Duration {
+    Duration::from_millis(millis)
+}
+
+/// Default settings, without heartbeats as there is no firmware to exchange
+/// them with, see `Harness::new`.
+fn config() -> Config {
+    let mut config = Config::default();
+    config.heartbeat.period = 0.;
+    config.heartbeat.timeout = 0.;
+    config
+}
+
+/// Limits high enough for the stick deflection to be followed at once.
+fn unlimited() -> Config {
+    let mut config = config();
+    let limits = Limits {
+        acceleration: 1e9,
+        jerk: 1e12,
+    };
+    config.planner.translation = limits;
+    config.planner.rotation = limits;
+    config
+}
+
+fn axis(axis: Axis, value: f32) -> Control {
+    Control::Joystick {
+        event: Joystick::AxisChanged(axis, value),
+    }
+}
+
+fn released(button: Button) -> Control {
+    Control::Joystick {
+        event: Joystick::ButtonReleased(button),
+    }
+}
+
+fn key(key: char) -> Control {
+    Control::Keyboard {
+        keycode: key as i32,
+    }
+}
+
+fn last_move(commands: &[Command]) -> Option<&Command> {
+    commands.iter().rev().find(|command| match **command {
+        Command::MoveTo { .. } => true,
+        _ => false,
+    })
+}
+
+#[test]
+fn manual_holds_its_position_without_input() {
+    let mut harness = Harness::new::<Manual>().unwrap();
+    assert_eq!(harness.commands(), vec![Command::SetAbsolute]);
+
+    harness.advance(ms(100)).unwrap();
+    assert_eq!(
+        harness.commands(),
+        vec![
+            Command::MoveTo { x: Some(Millimeters(1350.)), y: Some(Millimeters(1800.)), z: Some(Millimeters(400.)), f: Some(MillimetersPerMinute(6000.)) },
+            Command::RotateTo { u: Some(Degrees(0.)), v: Some(Degrees(0.)), f: Some(DegreesPerMinute(2000.)) },
+        ]
+    );
+}
+
+#[test]
+fn manual_integrates_the_stick_deflection() {
+    let mut harness = Harness::with_config::<Manual>(unlimited()).unwrap();
+    harness
+        .play(vec![
+            (ms(0), axis(Axis::LeftStickX, 1.)),
+            (ms(1000), axis(Axis::LeftStickX, 0.)),
+        ])
+        .unwrap();
+    harness.advance(ms(500)).unwrap();
+
+    // One second at full speed, 6000 mm/min
+    let commands = harness.commands();
+    assert_eq!(commands.iter().filter(|command| **command != Command::SetAbsolute).count(), 30);
+    assert_eq!(
+        last_move(&commands),
+        Some(&Command::MoveTo { x: Some(Millimeters(1450.)), y: Some(Millimeters(1800.)), z: Some(Millimeters(400.)), f: Some(MillimetersPerMinute(6000.)) })
+    );
+}
+
+#[test]
+fn manual_speed_keys_change_the_feed_rate() {
+    let mut harness = Harness::with_config::<Manual>(unlimited()).unwrap();
+    harness.play(vec![(ms(0), key('w')), (ms(0), axis(Axis::LeftStickY, -1.))]).unwrap();
+    harness.advance(ms(500)).unwrap();
+
+    assert_eq!(
+        last_move(&harness.commands()),
+        Some(&Command::MoveTo { x: Some(Millimeters(1350.)), y: Some(Millimeters(1700.)), z: Some(Millimeters(400.)), f: Some(MillimetersPerMinute(12000.)) })
+    );
+}
+
+#[test]
+fn calibration_moves_the_selected_motor() {
+    let mut harness = Harness::new::<Calibration>().unwrap();
+    harness
+        .play(vec![
+            (ms(0), released(Button::DPadUp)),
+            (ms(0), axis(Axis::LeftStickY, 1.)),
+            (ms(300), axis(Axis::LeftStickY, 0.)),
+        ])
+        .unwrap();
+
+    let commands = harness.commands();
+    assert_eq!(commands[0], Command::SetAbsolute);
+    assert_eq!(commands.len(), 4);
+    for (command, &length) in commands[1..].iter().zip([50. / 3., 100. / 3., 50.].iter()) {
+        match *command {
+            Command::MoveMotorTo { m: 1, l, f: Some(f) } => {
+                assert!((l.0 - length).abs() < 1e-3, "{:?}", command);
+                assert_eq!(f, MillimetersPerMinute(10000.));
+            }
+            _ => panic!("unexpected {:?}", command),
+        }
+    }
+
+    harness.control(released(Button::Select)).unwrap();
+    assert_eq!(harness.commands(), vec![Command::SetMotorZero { m: 1 }]);
+    harness.advance(ms(100)).unwrap();
+    assert_eq!(harness.commands(), vec![Command::MoveMotorTo { m: 1, l: Millimeters(0.), f: Some(MillimetersPerMinute(10000.)) }]);
+}
+
+#[test]
+fn calibration_moves_groups_and_independent_motors() {
+    let mut harness = Harness::new::<Calibration>().unwrap();
+    // The last group lifts with all four motors
+    for _ in 0..6 {
+        harness.control(released(Button::DPadUp)).unwrap();
+    }
+    harness.commands();
+    harness.control(axis(Axis::LeftStickY, 1.)).unwrap();
+    harness.advance(ms(100)).unwrap();
+    let speed = Some(MillimetersPerMinute(10000.));
+    let commands = harness.commands();
+    assert_eq!(commands.len(), 4);
+    for (m, command) in commands.iter().enumerate() {
+        match *command {
+            Command::MoveMotorTo { m: motor, l, f } => {
+                assert_eq!((motor, f), (m, speed));
+                assert!((l.0 - 50. / 3.).abs() < 1e-3, "{:?}", command);
+            }
+            _ => panic!("unexpected {:?}", command),
+        }
+    }
+
+    // Each stick drives its own motor
+    harness.control(key('i')).unwrap();
+    harness.control(axis(Axis::LeftStickY, 0.)).unwrap();
+    harness.control(axis(Axis::RightStickX, -1.)).unwrap();
+    harness.advance(ms(100)).unwrap();
+    let lengths: Vec<f32> = harness
+        .commands()
+        .iter()
+        .map(|command| match *command {
+            Command::MoveMotorTo { l, .. } => l.0,
+            _ => panic!("unexpected {:?}", command),
+        })
+        .collect();
+    let expected = [50. / 3., 50. / 3., 50. / 3., 0.];
+    assert_eq!(lengths.len(), 4);
+    assert!(lengths.iter().zip(expected.iter()).all(|(l, e)| (l - e).abs() < 1e-3), "{:?}", lengths);
+}
+
+fn tensions(values: &[f32]) -> Event {
+    Event::Tension {
+        tensions: values.iter().map(|&value| Newtons(value)).collect(),
+    }
+}
+
+#[test]
+fn calibration_balances_the_cable_tensions() {
+    let mut harness = Harness::with_state::<Calibration>(config(), State::Homed).unwrap();
+    harness.commands();
+    // Ignored until the balancing is started
+    harness.event(tensions(&[10., 20., 20., 20.])).unwrap();
+    assert_eq!(harness.commands(), vec![]);
+
+    // The slack cable is wound in, the tight one let out, a bounded step each
+    harness.control(key('b')).unwrap();
+    harness.event(tensions(&[10., 20.5, 30.5, 20.])).unwrap();
+    let speed = Some(MillimetersPerMinute(300.));
+    assert_eq!(
+        harness.commands(),
+        vec![
+            Command::MoveMotorTo { m: 0, l: Millimeters(-2.), f: speed },
+            Command::MoveMotorTo { m: 2, l: Millimeters(2.), f: speed },
+        ]
+    );
+    // Readings are outdated until the 2 mm at 300 mm/min are done and settled
+    harness.advance(ms(800)).unwrap();
+    harness.event(tensions(&[12., 20., 28.5, 20.])).unwrap();
+    assert_eq!(harness.commands(), vec![]);
+    harness.advance(ms(200)).unwrap();
+    harness.commands();
+    harness.event(tensions(&[18., 20., 21.5, 20.])).unwrap();
+    assert_eq!(
+        harness.commands(),
+        vec![
+            Command::MoveMotorTo { m: 0, l: Millimeters(-3.), f: speed },
+            Command::MoveMotorTo { m: 2, l: Millimeters(2.75), f: speed },
+        ]
+    );
+    harness.advance(ms(1000)).unwrap();
+    harness.commands();
+
+    // A report missing cables is rejected rather than taken as balanced
+    assert!(harness.event(tensions(&[20., 20., 20.])).is_err());
+    assert_eq!(harness.machine().state(), State::Homed);
+
+    // Done once every cable is within tolerance
+    harness.event(tensions(&[20., 20., 20.5, 19.5])).unwrap();
+    harness.event(tensions(&[10., 20., 20., 20.])).unwrap();
+    assert_eq!(harness.commands(), vec![]);
+    assert_eq!(harness.machine().state(), State::Calibrated);
+
+    // An overload stops the balancing and whatever the firmware still has queued
+    harness.control(released(Button::East)).unwrap();
+    harness.event(tensions(&[10., 120., 20., 20.])).unwrap();
+    harness.event(tensions(&[10., 20., 20., 20.])).unwrap();
+    assert_eq!(harness.commands(), vec![Command::Shutdown]);
+    // and no motor moves any more
+    assert_eq!(harness.machine().state(), State::Faulted);
+    harness.control(axis(Axis::LeftStickY, 1.)).unwrap();
+    harness.advance(ms(500)).unwrap();
+    assert_eq!(harness.commands(), vec![]);
+}
+
+#[test]
+fn simulation_does_not_drive_the_machine() {
+    let mut harness = Harness::new::<Simulation>().unwrap();
+    harness.play(vec![(ms(0), axis(Axis::LeftStickX, 1.)), (ms(1000), key('w'))]).unwrap();
+    assert_eq!(harness.commands(), vec![Command::SetAbsolute]);
+}
+
+#[test]
+fn modes_cycle_on_the_same_driver() {
+    let mut harness = Harness::new::<Manual>().unwrap();
+    harness.next_mode().unwrap();
+    assert_eq!(harness.name(), "Calibration");
+    harness.next_mode().unwrap();
+    assert_eq!(harness.name(), "Simulation");
+    harness.next_mode().unwrap();
+    assert_eq!(harness.name(), "Homing");
+    harness.next_mode().unwrap();
+    assert_eq!(harness.name(), "Manual");
+    assert_eq!(harness.commands(), vec![Command::SetAbsolute; 4]);
+}
+
+#[test]
+fn motion_modes_wait_for_the_machine_state() {
+    let mut harness = Harness::with_state::<Homing>(config(), State::Unhomed).unwrap();
+    assert!(harness.next_mode().is_err());
+    assert_eq!(harness.name(), "Homing");
+    assert_eq!(harness.commands(), vec![]);
+
+    // The operator can take the responsibility
+    harness.machine().toggle_override();
+    harness.next_mode().unwrap();
+    assert_eq!(harness.name(), "Manual");
+    harness.machine().toggle_override();
+    harness.advance(ms(100)).unwrap();
+    harness.control(key('X')).unwrap();
+    assert_eq!(harness.commands(), vec![Command::SetAbsolute]);
+}
+
+fn origin(x: f32) -> Event {
+    Event::Position {
+        x: Millimeters(x),
+        y: Millimeters(0.),
+        z: Millimeters(0.),
+        u: Some(Degrees(0.)),
+        v: Some(Degrees(0.)),
+        motors: vec![],
+    }
+}
+
+#[test]
+fn homing_unlocks_the_motion_modes_once_verified() {
+    let mut harness = Harness::with_state::<Homing>(config(), State::Unhomed).unwrap();
+    harness.control(key('h')).unwrap();
+    assert_eq!(harness.commands(), vec![Command::MoveToHome { axes: vec![] }]);
+
+    // Positions streamed while moving are not taken as the result
+    harness.event(origin(0.)).unwrap();
+    harness.event(Event::Homed { axes: vec![CommandAxis::X, CommandAxis::Y, CommandAxis::Z] }).unwrap();
+    assert_eq!(harness.commands(), vec![]);
+    harness.event(Event::Homed { axes: vec![CommandAxis::U, CommandAxis::V] }).unwrap();
+    assert_eq!(harness.commands(), vec![Command::QueryPosition]);
+    assert!(harness.next_mode().is_err());
+
+    // Streamed before the home finished, but received after it
+    harness.event(origin(250.)).unwrap();
+    assert_eq!(harness.machine().state(), State::Unhomed);
+    harness.event(origin(0.005)).unwrap();
+    assert_eq!(harness.machine().state(), State::Homed);
+    // Manual waits for the cables to be balanced
+    harness.next_mode().unwrap();
+    assert_eq!(harness.name(), "Calibration");
+}
+
+#[test]
+fn motion_modes_need_the_cables_balanced() {
+    assert!(Harness::with_state::<Manual>(config(), State::Homed).is_err());
+    assert!(Harness::with_state::<Simulation>(config(), State::Homed).is_err());
+    assert!(Harness::with_state::<Calibration>(config(), State::Homed).is_ok());
+    assert!(Harness::with_state::<Calibration>(config(), State::Unhomed).is_err());
+
+    let mut harness = Harness::with_state::<Manual>(config(), State::Calibrated).unwrap();
+    harness.machine().set(State::Homed);
+    harness.next_mode().unwrap();
+    assert_eq!(harness.name(), "Calibration");
+    // Simulation is skipped
+    harness.next_mode().unwrap();
+    assert_eq!(harness.name(), "Homing");
+}
+
+#[test]
+fn homing_stays_reachable_after_a_fault() {
+    let mut harness = Harness::new::<Calibration>().unwrap();
+    harness.machine().set(State::Faulted);
+    harness.next_mode().unwrap();
+    assert_eq!(harness.name(), "Homing");
+    assert!(harness.next_mode().is_err());
+    assert_eq!(harness.name(), "Homing");
+}
+
+#[test]
+fn homing_fails_on_timeout_or_wrong_position() {
+    let mut config = config();
+    config.homing.timeout = 1.;
+    let mut harness = Harness::with_state::<Homing>(config, State::Unhomed).unwrap();
+    harness.control(released(Button::South)).unwrap();
+    harness.advance(ms(1000)).unwrap();
+    assert_eq!(harness.commands(), vec![Command::MoveToHome { axes: vec![] }]);
+    harness.advance(ms(100)).unwrap();
+    assert_eq!(harness.commands(), vec![Command::Shutdown]);
+
+    // Homed axes must end up at the configured home
+    harness.control(key('h')).unwrap();
+    harness.event(Event::Homed { axes: vec![] }).unwrap();
+    harness.event(Event::Homed { axes: vec![CommandAxis::X, CommandAxis::Y, CommandAxis::Z, CommandAxis::U, CommandAxis::V] }).unwrap();
+    harness.event(origin(3.)).unwrap();
+    harness.advance(ms(1000)).unwrap();
+    assert_eq!(harness.commands(), vec![Command::MoveToHome { axes: vec![] }, Command::QueryPosition]);
+    harness.advance(ms(1000)).unwrap();
+    assert_eq!(harness.commands(), vec![Command::Shutdown]);
+    assert_eq!(harness.machine().state(), State::Faulted);
+    assert!(harness.next_mode().is_err());
+}
+
+#[test]
+fn homing_checks_the_configured_home() {
+    let mut config = config();
+    config.homing.position = [3., 0., 0., 0., 0.];
+    let mut harness = Harness::with_state::<Homing>(config, State::Unhomed).unwrap();
+    harness.control(key('h')).unwrap();
+    harness.event(Event::Homed { axes: vec![CommandAxis::X, CommandAxis::Y, CommandAxis::Z, CommandAxis::U, CommandAxis::V] }).unwrap();
+    harness.event(origin(0.)).unwrap();
+    assert_eq!(harness.machine().state(), State::Unhomed);
+    harness.event(origin(3.)).unwrap();
+    assert_eq!(harness.machine().state(), State::Homed);
+}
+
+#[test]
+fn manual_integrates_the_measured_time_between_ticks() {
+    let mut harness = Harness::with_config::<Manual>(unlimited()).unwrap();
+    harness.control(axis(Axis::LeftStickX, 1.)).unwrap();
+
+    // Jittery ticks still add up to one second at 6000 mm/min
+    for &millis in [150, 50, 130, 70, 300, 200, 100].iter() {
+        harness.clock().advance(ms(millis));
+        harness.tick().unwrap();
+    }
+    assert_eq!(harness.now(), ms(1000));
+    assert_eq!(
+        last_move(&harness.commands()),
+        Some(&Command::MoveTo { x: Some(Millimeters(1450.)), y: Some(Millimeters(1800.)), z: Some(Millimeters(400.)), f: Some(MillimetersPerMinute(6000.)) })
+    );
+}
+
+fn move_x(command: &Command) -> Option<f32> {
+    match *command {
+        Command::MoveTo { x, .. } => x.map(|x| x.0),
+        _ => None,
+    }
+}
+
+#[test]
+fn manual_ramps_the_velocity_within_the_planner_limits() {
+    let mut harness = Harness::new::<Manual>().unwrap();
+    harness
+        .play(vec![
+            (ms(0), axis(Axis::LeftStickX, 1.)),
+            (ms(1000), axis(Axis::LeftStickX, 0.)),
+        ])
+        .unwrap();
+    harness.advance(ms(1000)).unwrap();
+
+    let xs: Vec<f32> = harness.commands().iter().filter_map(move_x).collect();
+    // Slower than the stick asks for at first, then at full speed
+    assert!(xs[0] - 1350. < 10.);
+    assert!((xs[9] - xs[8] - 10.).abs() < 1e-3);
+    // Each tick changes the velocity by at most 500 mm/s² * 0.1 s
+    for window in xs.windows(3) {
+        let change = (window[2] - window[1]) - (window[1] - window[0]);
+        assert!(change.abs() <= 5. + 1e-3, "{:?}", window);
+    }
+    // The machine coasts to a stop after the release, never backing up
+    assert!(xs[10] > xs[9]);
+    assert!(xs.windows(2).all(|pair| pair[1] >= pair[0]));
+    assert_eq!(xs[xs.len() - 1], xs[xs.len() - 2]);
+}
+
+#[test]
+fn manual_keeps_sub_millimeter_motion() {
+    let mut harness = Harness::new::<Manual>().unwrap();
+    let mut script: Vec<(Duration, Control)> = (0..4).map(|_| (ms(0), key('s'))).collect();
+    script.push((ms(0), axis(Axis::LeftStickX, 0.5)));
+    harness.play(script).unwrap();
+    harness.advance(ms(2000)).unwrap();
+
+    // 600 mm/min at an eighth of the full deflection, 0.125 mm per tick
+    let xs: Vec<f32> = harness.commands().iter().filter_map(move_x).collect();
+    assert!(xs.windows(2).all(|pair| pair[1] > pair[0]), "{:?}", xs);
+    assert!((xs[19] - xs[18] - 0.125).abs() < 1e-3, "{:?}", xs);
+}
+
+#[test]
+fn manual_jogs_with_relative_moves() {
+    let mut config = unlimited();
+    config.manual.relative = true;
+    let mut harness = Harness::with_config::<Manual>(config).unwrap();
+    assert_eq!(harness.commands(), vec![Command::SetRelative]);
+
+    // Nothing is sent while the stick is at rest
+    harness.advance(ms(500)).unwrap();
+    assert_eq!(harness.commands(), vec![]);
+
+    harness.play(vec![(ms(500), axis(Axis::LeftStickX, 1.)), (ms(1500), axis(Axis::LeftStickX, 0.))]).unwrap();
+    harness.advance(ms(500)).unwrap();
+    let steps: Vec<f32> = harness.commands().iter().filter_map(move_x).collect();
+    assert_eq!(steps.len(), 10);
+    assert!(steps.iter().all(|step| (step - 10.).abs() < 1e-3), "{:?}", steps);
+}
+
+#[test]
+fn manual_waits_for_the_machine_position_before_moving() {
+    let mut config = unlimited();
+    config.manual.sync = true;
+    let mut harness = Harness::with_config::<Manual>(config).unwrap();
+    assert_eq!(harness.commands(), vec![Command::SetAbsolute, Command::QueryPosition]);
+
+    harness.control(axis(Axis::LeftStickX, 1.)).unwrap();
+    harness.advance(ms(500)).unwrap();
+    assert_eq!(harness.commands(), vec![]);
+
+    harness
+        .event(Event::Position {
+            x: Millimeters(100.),
+            y: Millimeters(200.),
+            z: Millimeters(300.),
+            u: Some(Degrees(1.)),
+            v: None,
+            motors: vec![],
+        })
+        .unwrap();
+    harness.advance(ms(100)).unwrap();
+    assert_eq!(
+        harness.commands(),
+        vec![
+            Command::MoveTo {
+                x: Some(Millimeters(110.)),
+                y: Some(Millimeters(200.)),
+                z: Some(Millimeters(300.)),
+                f: Some(MillimetersPerMinute(6000.)),
+            },
+            Command::RotateTo { u: Some(Degrees(1.)), v: Some(Degrees(0.)), f: Some(DegreesPerMinute(2000.)) },
+        ]
+    );
+}
+
+#[test]
+fn manual_jogs_one_increment_per_press() {
+    let mut harness = Harness::new::<Manual>().unwrap();
+    harness.commands();
+
+    harness.control(key('X')).unwrap();
+    assert_eq!(
+        harness.commands(),
+        vec![Command::MoveTo {
+            x: Some(Millimeters(1351.)),
+            y: Some(Millimeters(1800.)),
+            z: Some(Millimeters(400.)),
+            f: Some(MillimetersPerMinute(6000.)),
+        }]
+    );
+
+    // Down to 0.1, then backwards along Y with the D-pad
+    harness.control(key('[')).unwrap();
+    harness.control(released(Button::DPadDown)).unwrap();
+    harness.control(key('u')).unwrap();
+    assert_eq!(
+        harness.commands(),
+        vec![
+            Command::MoveTo {
+                x: Some(Millimeters(1351.)),
+                y: Some(Millimeters(1799.9)),
+                z: Some(Millimeters(400.)),
+                f: Some(MillimetersPerMinute(6000.)),
+            },
+            Command::RotateTo { u: Some(Degrees(-0.1)), v: Some(Degrees(0.)), f: Some(DegreesPerMinute(2000.)) },
+        ]
+    );
+
+    // The position the machine was jogged to is held afterwards
+    harness.advance(ms(100)).unwrap();
+    assert_eq!(move_x(&harness.commands()[0]), Some(1351.));
+}
+
+#[test]
+fn manual_jogs_relative_increments() {
+    let mut config = config();
+    config.manual.relative = true;
+    let mut harness = Harness::with_config::<Manual>(config).unwrap();
+    harness.commands();
+
+    for _ in 0..2 {
+        harness.control(released(Button::RightTrigger)).unwrap();
+    }
+    harness.control(released(Button::North)).unwrap();
+    assert_eq!(
+        harness.commands(),
+        vec![Command::MoveTo {
+            x: Some(Millimeters(0.)),
+            y: Some(Millimeters(0.)),
+            z: Some(Millimeters(100.)),
+            f: Some(MillimetersPerMinute(6000.)),
+        }]
+    );
+}
+
+#[test]
+fn calibration_jogs_the_selected_motor() {
+    let mut harness = Harness::new::<Calibration>().unwrap();
+    harness.control(released(Button::DPadUp)).unwrap();
+    harness.control(released(Button::DPadRight)).unwrap();
+    harness.control(key(']')).unwrap();
+    harness.control(key(',')).unwrap();
+    assert_eq!(
+        harness.commands(),
+        vec![
+            Command::SetAbsolute,
+            Command::MoveMotorTo { m: 1, l: Millimeters(1.), f: Some(MillimetersPerMinute(10000.)) },
+            Command::MoveMotorTo { m: 1, l: Millimeters(-9.), f: Some(MillimetersPerMinute(10000.)) },
+        ]
+    );
+}
+
+#[test]
+fn manual_moves_along_the_tilted_platform() {
+    let mut harness = Harness::with_config::<Manual>(unlimited()).unwrap();
+    // Tilt the platform by 90° around X, then push the stick forwards
+    harness.control(key('f')).unwrap();
+    harness.control(key(']')).unwrap();
+    harness.control(key(']')).unwrap();
+    harness.control(key('U')).unwrap();
+    harness.control(key('[')).unwrap();
+    harness.control(key('u')).unwrap();
+    harness.commands();
+
+    harness.control(axis(Axis::LeftStickY, 1.)).unwrap();
+    harness.advance(ms(100)).unwrap();
+    // Forward on the platform is up in the world
+    assert_near(last_move(&harness.commands()), [1350., 1800., 410.]);
+
+    // Back in the world frame, forward is along Y again
+    harness.control(released(Button::Select)).unwrap();
+    harness.advance(ms(100)).unwrap();
+    assert_near(last_move(&harness.commands()), [1350., 1810., 410.]);
+}
+
+fn assert_near(command: Option<&Command>, expected: [f32; 3]) {
+    match command {
+        Some(&Command::MoveTo { x: Some(x), y: Some(y), z: Some(z), .. }) => {
+            let actual = [x.0, y.0, z.0];
+            for (a, e) in actual.iter().zip(expected.iter()) {
+                assert!((a - e).abs() < 1e-3, "{:?} instead of {:?}", actual, expected);
+            }
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn manual_returns_to_a_taught_waypoint() {
+    let mut harness = Harness::new::<Manual>().unwrap();
+    harness.control(key('t')).unwrap();
+    harness.control(key(']')).unwrap();
+    harness.control(key(']')).unwrap();
+    for _ in 0..3 {
+        harness.control(key('X')).unwrap();
+    }
+    harness.control(released(Button::East)).unwrap();
+    harness.commands();
+
+    // Back to the first waypoint within the planner limits, then held there
+    harness.control(key('1')).unwrap();
+    harness.advance(ms(5000)).unwrap();
+    let xs: Vec<f32> = harness.commands().iter().filter_map(move_x).collect();
+    assert!(xs.len() > 10, "{:?}", xs);
+    assert!(xs.windows(2).all(|pair| pair[0] - pair[1] <= 10. + 1e-3), "{:?}", xs);
+    assert_eq!(xs[xs.len() - 1], 1350.);
+
+    // The West button cycles to the next waypoint
+    harness.control(released(Button::West)).unwrap();
+    harness.advance(ms(5000)).unwrap();
+    assert_near(last_move(&harness.commands()), [1650., 1800., 400.]);
+}
+
+#[test]
+fn manual_follows_the_waypoint_trajectory_in_time() {
+    let mut harness = Harness::new::<Manual>().unwrap();
+    harness.control(key('t')).unwrap();
+    harness.control(key(']')).unwrap();
+    harness.control(key(']')).unwrap();
+    for _ in 0..3 {
+        harness.control(key('X')).unwrap();
+    }
+    harness.commands();
+
+    // Ticks alternately early and late still move at the set speed, 100 mm/s
+    harness.control(key('1')).unwrap();
+    let mut last = 1650.;
+    let mut elapsed = Duration::from_secs(0);
+    for index in 0..80 {
+        let dt = ms(if index % 2 == 0 { 50 } else { 150 });
+        elapsed += dt;
+        harness.clock().set(elapsed);
+        harness.tick().unwrap();
+        let x = harness.commands().iter().filter_map(move_x).last().unwrap();
+        assert!(last - x <= 100. * seconds(dt) as f32 + 1e-3, "{} to {} in {:?}", last, x, dt);
+        last = x;
+    }
+    assert_eq!(last, 1350.);
+}
+
+#[test]
+fn manual_exports_the_taught_waypoints() {
+    let path = std::env::temp_dir().join("fssp_waypoints.gcode");
+    let mut config = config();
+    config.manual.program = path.to_string_lossy().into_owned();
+    let mut harness = Harness::with_config::<Manual>(config).unwrap();
+    harness.control(key('t')).unwrap();
+    harness.control(key('Z')).unwrap();
+    harness.control(key('V')).unwrap();
+    harness.control(key('t')).unwrap();
+    harness.control(key('g')).unwrap();
+
+    let program = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(
+        program,
+        "G90\n\
+         G1 X1350 Y1800 Z400 F6000\nG1 U0 V0 F2000\n\
+         G1 X1350 Y1800 Z401 F6000\nG1 U0 V1 F2000\n"
+    );
+}
+
+#[test]
+fn manual_teaches_only_from_a_synced_position() {
+    let path = std::env::temp_dir().join("fssp_unsynced.gcode");
+    for &relative in [false, true].iter() {
+        let mut config = config();
+        config.manual.relative = relative;
+        config.manual.sync = true;
+        config.manual.program = path.to_string_lossy().into_owned();
+        let mut harness = Harness::with_config::<Manual>(config).unwrap();
+        harness.control(key('t')).unwrap();
+        harness.control(key('g')).unwrap();
+        assert!(!path.exists());
+
+        harness.event(origin(100.)).unwrap();
+        harness.control(key('t')).unwrap();
+        harness.control(key('g')).unwrap();
+        let program = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(program, "G90\nG1 X100 Y0 Z0 F6000\nG1 U0 V0 F2000\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
+
+fn joystick(event: Joystick) -> Control {
+    Control::Joystick { event: event }
+}
+
+#[test]
+fn manual_stops_without_input_or_gamepad() {
+    let mut config = unlimited();
+    config.watchdog.timeout = 0.5;
+    let mut harness = Harness::with_config::<Manual>(config).unwrap();
+    harness.control(axis(Axis::LeftStickX, 1.)).unwrap();
+    harness.advance(ms(1000)).unwrap();
+    // Moving for the 0.5 s before the timeout, 10 mm per tick
+    assert_near(last_move(&harness.commands()), [1400., 1800., 400.]);
+
+    harness.control(axis(Axis::LeftStickX, 1.)).unwrap();
+    harness.advance(ms(200)).unwrap();
+    harness.control(joystick(Joystick::Disconnected)).unwrap();
+    harness.advance(ms(200)).unwrap();
+    assert_near(last_move(&harness.commands()), [1420., 1800., 400.]);
+}
+
+#[test]
+fn manual_keeps_a_steady_stick_by_default() {
+    let mut harness = Harness::with_config::<Manual>(unlimited()).unwrap();
+    harness.control(axis(Axis::LeftStickX, 1.)).unwrap();
+    harness.advance(ms(5000)).unwrap();
+    assert_near(last_move(&harness.commands()), [1850., 1800., 400.]);
+}
+
+#[test]
+fn manual_cancels_the_waypoint_go_to_on_release() {
+    let mut harness = Harness::new::<Manual>().unwrap();
+    harness.control(key('t')).unwrap();
+    harness.control(key(']')).unwrap();
+    harness.control(key(']')).unwrap();
+    for _ in 0..3 {
+        harness.control(key('X')).unwrap();
+    }
+    harness.control(key('1')).unwrap();
+    harness.advance(ms(500)).unwrap();
+    let xs: Vec<f32> = harness.commands().iter().filter_map(move_x).collect();
+    let stopped = xs[xs.len() - 1];
+    assert!(stopped < 1650. && stopped > 1350., "{:?}", xs);
+
+    // Held where it was, rather than resuming once the gamepad is back
+    harness.control(joystick(Joystick::Disconnected)).unwrap();
+    harness.advance(ms(5000)).unwrap();
+    let xs: Vec<f32> = harness.commands().iter().filter_map(move_x).collect();
+    assert!(xs.iter().all(|&x| (x - stopped).abs() < 1e-3), "{:?}", xs);
+}
+
+#[test]
+fn manual_moves_only_while_the_hold_button_is_down() {
+    let mut config = unlimited();
+    config.watchdog.hold = Some(Button::LeftThumb);
+    let mut harness = Harness::with_config::<Manual>(config).unwrap();
+    harness.control(axis(Axis::LeftStickX, 1.)).unwrap();
+    harness.advance(ms(500)).unwrap();
+    assert_near(last_move(&harness.commands()), [1350., 1800., 400.]);
+
+    // The stick deflected beforehand counts once the button is down
+    harness.control(joystick(Joystick::ButtonPressed(Button::LeftThumb))).unwrap();
+    harness.control(joystick(Joystick::ButtonChanged(Button::LeftThumb, 1.))).unwrap();
+    harness.advance(ms(3000)).unwrap();
+    assert_near(last_move(&harness.commands()), [1650., 1800., 400.]);
+
+    harness.control(joystick(Joystick::ButtonReleased(Button::LeftThumb))).unwrap();
+    harness.advance(ms(500)).unwrap();
+    assert_near(last_move(&harness.commands()), [1650., 1800., 400.]);
+}
+
+#[test]
+fn manual_jogs_only_while_the_hold_button_is_down() {
+    let mut config = config();
+    config.watchdog.hold = Some(Button::LeftThumb);
+    let mut harness = Harness::with_config::<Manual>(config).unwrap();
+    harness.control(key('t')).unwrap();
+    harness.commands();
+    harness.control(key('X')).unwrap();
+    harness.control(released(Button::DPadRight)).unwrap();
+    harness.control(released(Button::West)).unwrap();
+    harness.advance(ms(1000)).unwrap();
+    assert!(harness.commands().iter().filter_map(move_x).all(|x| x == 1350.));
+
+    harness.control(joystick(Joystick::ButtonPressed(Button::LeftThumb))).unwrap();
+    harness.control(key('X')).unwrap();
+    harness.control(released(Button::DPadRight)).unwrap();
+    assert_eq!(harness.commands().iter().filter_map(move_x).collect::<Vec<f32>>(), vec![1351., 1352.]);
+
+    // Switching modes moves nothing
+    harness.control(joystick(Joystick::ButtonReleased(Button::LeftThumb))).unwrap();
+    harness.control(key('m')).unwrap();
+    assert_eq!(harness.name(), "Calibration");
+}
+
+#[test]
+fn heartbeats_are_sent_and_awaited() {
+    let mut heartbeat = Heartbeat::new(&Config::default().heartbeat);
+    let beats: Vec<Beat> = (0..25).map(|_| heartbeat.tick(ms(100))).collect();
+    let sent: Vec<usize> = (0..25).filter(|&tick| beats[tick].send).collect();
+    assert_eq!(sent, vec![4, 9, 14, 19, 24]);
+    // Lost once, after 2 s without news from the firmware
+    let lost: Vec<usize> = (0..25).filter(|&tick| beats[tick].lost).collect();
+    assert_eq!(lost, vec![20]);
+    assert!(heartbeat.is_lost());
+
+    assert!(heartbeat.event());
+    assert!(!heartbeat.event());
+    assert!(!heartbeat.tick(ms(1500)).lost);
+}
+
+/// Run the master loop over `messages` on a calibrated machine, returning the
+/// commands sent and the machine state.
+fn run(config: Config, messages: Vec<Message>) -> (Vec<Command>, State) {
+    let (tx, rx) = mpsc::channel();
+    let (driver, commands) = mpsc::channel();
+    for message in messages {
+        tx.send(message).unwrap();
+    }
+    drop(tx);
+    let machine = Tracker::new(State::Calibrated, false);
+    let context = Context {
+        driver: Guard::new(driver, &machine),
+        config: config,
+        machine: machine.clone(),
+    };
+    master_loop(rx, context, Recorder::disabled()).unwrap();
+    let state = machine.state();
+    (commands.try_iter().collect(), state)
+}
+
+fn ticks(count: usize) -> Vec<Message> {
+    (0..count).map(|_| Message::Tick(ms(100))).collect()
+}
+
+fn quiet_firmware(action: Action) -> Config {
+    let mut config = Config::default();
+    config.heartbeat.period = 0.5;
+    config.heartbeat.timeout = 1.;
+    config.heartbeat.action = action;
+    config
+}
+
+fn count(commands: &[Command], wanted: fn(&Command) -> bool) -> usize {
+    commands.iter().filter(|&command| wanted(command)).count()
+}
+
+fn is_move(command: &Command) -> bool {
+    match *command {
+        Command::MoveTo { .. } => true,
+        _ => false,
+    }
+}
+
+fn is_heartbeat(command: &Command) -> bool {
+    *command == Command::Heartbeat
+}
+
+#[test]
+fn master_loop_pauses_the_mode_while_the_firmware_is_quiet() {
+    // Lost on the 11th tick, back with the event
+    let mut messages = ticks(35);
+    messages.push(Message::Event(origin(1350.)));
+    messages.extend(ticks(5));
+    let (commands, state) = run(quiet_firmware(Action::Pause), messages);
+
+    // Manual sends its target on every tick it gets
+    assert_eq!(count(&commands, is_move), 15);
+    // while the heartbeats go on
+    assert_eq!(count(&commands, is_heartbeat), 8);
+    let moves: Vec<usize> = (0..commands.len()).filter(|&index| is_move(&commands[index])).collect();
+    assert!(moves[10] > moves[9] + 5, "{:?}", commands);
+    assert_eq!(state, State::Calibrated);
+}
+
+#[test]
+fn master_loop_halts_the_machine_when_the_firmware_is_gone() {
+    let mut messages = ticks(20);
+    messages.push(Message::Control(key('m')));
+    let (commands, state) = run(quiet_firmware(Action::Stop), messages);
+
+    let shutdown = commands.iter().position(|command| *command == Command::Shutdown).unwrap();
+    assert_eq!(count(&commands[..shutdown], is_move), 10);
+    assert_eq!(count(&commands[shutdown..], is_move), 0);
+    assert_eq!(state, State::Faulted);
+
+    // The mode trigger skipped to Homing, which can still home the machine
+    let mut messages = ticks(20);
+    messages.push(Message::Control(key('m')));
+    messages.push(Message::Control(key('h')));
+    let (commands, _) = run(quiet_firmware(Action::Stop), messages);
+    assert_eq!(commands.last(), Some(&Command::MoveToHome { axes: vec![] }));
+}
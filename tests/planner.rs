@@ -0,0 +1,59 @@
+extern crate fssp_simulator;
+
+use fssp_simulator::planner::{plan_segment, Limits, Profile};
+use std::time::Duration;
+
+const LIMITS: Limits = Limits {
+    acceleration: 500.,
+    jerk: 5_000.,
+};
+
+fn dt() -> Duration {
+    Duration::from_millis(10)
+}
+
+#[test]
+fn profile_reaches_the_target_velocity_within_the_limits() {
+    let mut profile = Profile::new();
+    let mut previous = profile;
+    for _ in 0..100 {
+        profile.track(100., &LIMITS, dt());
+        assert!(profile.acceleration.abs() <= LIMITS.acceleration);
+        assert!((profile.acceleration - previous.acceleration).abs() <= LIMITS.jerk * 0.01 + 1e-3);
+        assert!(profile.velocity <= 100.);
+        previous = profile;
+    }
+    assert_eq!(profile.velocity, 100.);
+
+    for _ in 0..100 {
+        profile.track(0., &LIMITS, dt());
+    }
+    assert!(profile.is_idle());
+}
+
+#[test]
+fn segment_ends_exactly_on_its_target() {
+    let points = plan_segment(&[0., 0.], &[30., 40.], 100., &LIMITS, dt());
+    assert_eq!(points.last(), Some(&vec![30., 40.]));
+    // Never further than the end, never backing up
+    let distances: Vec<f32> = points.iter().map(|p| (p[0] * p[0] + p[1] * p[1]).sqrt()).collect();
+    assert!(distances.windows(2).all(|pair| pair[1] >= pair[0] - 1e-3));
+    assert!(distances.iter().all(|distance| *distance <= 50. + 1e-3));
+}
+
+#[test]
+fn segment_respects_the_cruise_speed_and_acceleration() {
+    let points = plan_segment(&[0.], &[100.], 50., &LIMITS, dt());
+    let velocities: Vec<f32> = points.windows(2).map(|pair| (pair[1][0] - pair[0][0]) / 0.01).collect();
+    assert!(velocities.iter().all(|velocity| *velocity <= 50. + 1e-2));
+    for pair in velocities.windows(2).take(velocities.len() - 2) {
+        assert!((pair[1] - pair[0]).abs() / 0.01 <= LIMITS.acceleration + 1.);
+    }
+    // Two seconds at cruise speed, plus the ramps
+    assert!(points.len() > 200 && points.len() < 260, "{}", points.len());
+}
+
+#[test]
+fn empty_segment_is_a_single_point() {
+    assert_eq!(plan_segment(&[1., 2.], &[1., 2.], 100., &LIMITS, dt()), vec![vec![1., 2.]]);
+}
@@ -0,0 +1,74 @@
+extern crate fssp_simulator;
+
+use fssp_simulator::config::{Config, Transport};
+use fssp_simulator::controller::control::Control;
+use fssp_simulator::driver::command::Command;
+use fssp_simulator::driver::connect_driver;
+use fssp_simulator::message::Message;
+use fssp_simulator::mode::{master_loop, Context};
+use fssp_simulator::recorder::{connect_recorder, Entry};
+use fssp_simulator::replay::{load_session, replay};
+use fssp_simulator::state::{Guard, State, Tracker};
+use std::env;
+use std::fs;
+use std::process;
+use std::sync::mpsc;
+use std::time::Duration;
+
+fn temporary(name: &str) -> String {
+    let path = env::temp_dir().join(format!("fssp-{}-{}", process::id(), name));
+    path.to_string_lossy().into_owned()
+}
+
+#[test]
+fn recorded_session_replays_without_divergence() {
+    let session = temporary("session.jsonl");
+    let output = temporary("session.gcode");
+    // As main runs it without any firmware to exchange heartbeats with
+    let mut config = Config::default();
+    config.heartbeat.period = 0.;
+    config.heartbeat.timeout = 0.;
+
+    // Same sequence as main: master loop, then the final halt
+    let (recorder, recorder_link) = connect_recorder(&session).unwrap();
+    let (tx, _rx) = mpsc::channel();
+    let transport = Transport::File { path: output.clone() };
+    let (driver, driver_link) = connect_driver(&transport, config.driver.precision, &tx, &recorder).unwrap();
+    let halt = driver.clone();
+    let machine = Tracker::new(State::Unhomed, !config.homing.required);
+    let context = Context {
+        driver: Guard::new(driver, &machine),
+        config: config.clone(),
+        machine: machine,
+    };
+    let (messages, input) = mpsc::channel();
+    for _ in 0..30 {
+        messages.send(Message::Tick(Duration::from_millis(100))).unwrap();
+    }
+    messages.send(Message::Control(Control::Keyboard { keycode: 'h' as i32 })).unwrap();
+    messages.send(Message::Tick(Duration::from_millis(100))).unwrap();
+    messages.send(Message::Quit).unwrap();
+    master_loop(input, context, recorder).unwrap();
+    halt.send(Command::Shutdown).unwrap();
+    drop(halt);
+    drop(tx);
+    assert!(driver_link.close(Duration::from_secs(1)));
+    assert!(recorder_link.close(Duration::from_secs(1)));
+
+    let recorded: Vec<Command> = load_session(&session)
+        .unwrap()
+        .into_iter()
+        .filter_map(|record| match record.entry {
+            Entry::Command(command) => Some(command),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(recorded.last(), Some(&Command::Shutdown));
+    assert!(recorded.contains(&Command::MoveToHome { axes: vec![] }));
+
+    assert_eq!(replay(&session, 0., None).unwrap(), vec![]);
+    // Heartbeats are only sent with the configuration given now
+    assert!(!replay(&session, 0., Some(&Config::default())).unwrap().is_empty());
+    fs::remove_file(&session).ok();
+    fs::remove_file(&output).ok();
+}
@@ -0,0 +1,222 @@
+extern crate fssp_simulator;
+extern crate libc;
+
+use fssp_simulator::config::{Config, Transport};
+use fssp_simulator::controller::take_terminal;
+use fssp_simulator::driver::command::{Command, DEFAULT_PRECISION};
+use fssp_simulator::driver::connect_driver;
+use fssp_simulator::message::Message;
+use fssp_simulator::recorder::Recorder;
+use fssp_simulator::sensor::connect_sensor;
+use fssp_simulator::sensor::event::Event;
+use fssp_simulator::units::Millimeters;
+use std::env;
+use std::ffi::CStr;
+use std::fs;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::io::FromRawFd;
+use std::os::unix::net::UnixListener;
+use std::process;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Master side of a new pseudo-terminal, standing for the firmware, and the
+/// path of its slave side.
+fn pty() -> (File, String) {
+    unsafe {
+        let master = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+        assert!(master >= 0, "cannot open a pseudo-terminal");
+        assert_eq!(libc::grantpt(master), 0);
+        assert_eq!(libc::unlockpt(master), 0);
+        let mut name = [0 as libc::c_char; 64];
+        assert_eq!(libc::ptsname_r(master, name.as_mut_ptr(), name.len()), 0);
+        let path = CStr::from_ptr(name.as_ptr()).to_string_lossy().into_owned();
+        (File::from_raw_fd(master), path)
+    }
+}
+
+/// Path in the temporary directory, unique to this test run.
+fn temporary(name: &str) -> String {
+    let path = env::temp_dir().join(format!("fssp-{}-{}", process::id(), name));
+    fs::remove_file(&path).ok();
+    path.to_string_lossy().into_owned()
+}
+
+fn serial(path: &str) -> Transport {
+    Transport::Serial {
+        path: String::from(path),
+        baud: 115200,
+    }
+}
+
+#[test]
+fn driver_link_speaks_over_a_serial_port() {
+    let (firmware, path) = pty();
+    let mut reply = firmware.try_clone().unwrap();
+    let mut lines = BufReader::new(firmware).lines();
+
+    let (tx, rx) = mpsc::channel();
+    let (driver, link) = connect_driver(&serial(&path), DEFAULT_PRECISION, &tx, &Recorder::disabled()).unwrap();
+    driver.send(Command::MoveMotorTo { m: 2, l: Millimeters(12.5), f: None }).unwrap();
+    driver.send(Command::QueryPosition).unwrap();
+    assert_eq!(lines.next().unwrap().unwrap(), "G6 M2 L12.5");
+    assert_eq!(lines.next().unwrap().unwrap(), "M114");
+
+    writeln!(reply, "homed X").unwrap();
+    match rx.recv_timeout(Duration::from_secs(2)) {
+        Ok(Message::Event(Event::Homed { axes })) => assert_eq!(axes.len(), 1),
+        other => panic!("unexpected {:?}", other),
+    }
+
+    drop(driver);
+    assert!(link.close(Duration::from_secs(1)));
+}
+
+#[test]
+fn sensor_link_reads_a_serial_port_until_closed() {
+    let (mut firmware, path) = pty();
+
+    let (tx, rx) = mpsc::channel();
+    let link = connect_sensor(&serial(&path), &tx).unwrap();
+    writeln!(firmware, "T0:20.5 T1:19").unwrap();
+    match rx.recv_timeout(Duration::from_secs(2)) {
+        Ok(Message::Event(Event::Tension { tensions })) => assert_eq!(tensions.len(), 2),
+        other => panic!("unexpected {:?}", other),
+    }
+    // Returns although the firmware side stays open
+    link.close();
+}
+
+#[test]
+fn serial_port_rejects_unknown_baud_rates() {
+    let (_firmware, path) = pty();
+    let transport = Transport::Serial {
+        path: path,
+        baud: 12345,
+    };
+    let (tx, _rx) = mpsc::channel();
+    assert!(connect_sensor(&transport, &tx).is_err());
+}
+
+#[test]
+fn transports_are_selected_in_the_configuration() {
+    let content = r#"
+        [driver.transport]
+        kind = "serial"
+        path = "/dev/ttyACM0"
+        baud = 115200
+    "#;
+    let config = Config::parse(content).unwrap();
+    assert_eq!(config.driver.transport, serial("/dev/ttyACM0"));
+    assert_eq!(config.sensor, Config::default().sensor);
+}
+
+#[test]
+fn driver_link_writes_the_gcode_to_a_file() {
+    let path = temporary("driver.gcode");
+    let transport = Transport::File { path: path.clone() };
+
+    let (tx, _rx) = mpsc::channel();
+    let (driver, link) = connect_driver(&transport, DEFAULT_PRECISION, &tx, &Recorder::disabled()).unwrap();
+    driver.send(Command::SetAbsolute).unwrap();
+    driver.send(Command::MoveMotorTo { m: 0, l: Millimeters(3.), f: None }).unwrap();
+    drop(driver);
+    assert!(link.close(Duration::from_secs(1)));
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "G90\nG6 M0 L3\n");
+    fs::remove_file(&path).ok();
+}
+
+#[test]
+fn sensor_link_reads_the_lines_of_a_file() {
+    let path = temporary("sensor.log");
+    fs::write(&path, "T0:20 T1:21\nhomed X Y\n").unwrap();
+    let transport = Transport::File { path: path.clone() };
+
+    let (tx, rx) = mpsc::channel();
+    let link = connect_sensor(&transport, &tx).unwrap();
+    let events: Vec<Message> = rx.iter().take(2).collect();
+    match events[0] {
+        Message::Event(Event::Tension { ref tensions }) => assert_eq!(tensions.len(), 2),
+        ref other => panic!("unexpected {:?}", other),
+    }
+    match events[1] {
+        Message::Event(Event::Homed { ref axes }) => assert_eq!(axes.len(), 2),
+        ref other => panic!("unexpected {:?}", other),
+    }
+    link.close();
+    fs::remove_file(&path).ok();
+}
+
+#[test]
+fn driver_link_speaks_over_a_unix_socket() {
+    let path = temporary("driver.sock");
+    let listener = UnixListener::bind(&path).unwrap();
+    let transport = Transport::Unix { path: path.clone() };
+
+    let (tx, rx) = mpsc::channel();
+    let (driver, link) = connect_driver(&transport, DEFAULT_PRECISION, &tx, &Recorder::disabled()).unwrap();
+    let (firmware, _) = listener.accept().unwrap();
+    let mut reply = firmware.try_clone().unwrap();
+    let mut lines = BufReader::new(firmware).lines();
+
+    driver.send(Command::Heartbeat).unwrap();
+    assert_eq!(lines.next().unwrap().unwrap(), "M7000");
+    writeln!(reply, "homed Z").unwrap();
+    match rx.recv_timeout(Duration::from_secs(2)) {
+        Ok(Message::Event(Event::Homed { axes })) => assert_eq!(axes.len(), 1),
+        other => panic!("unexpected {:?}", other),
+    }
+
+    drop(driver);
+    assert!(link.close(Duration::from_secs(1)));
+    assert!(lines.next().is_none());
+    fs::remove_file(&path).ok();
+}
+
+#[test]
+fn standard_streams_only_go_one_way() {
+    let (tx, _rx) = mpsc::channel();
+    assert!(connect_driver(&Transport::Stdin, DEFAULT_PRECISION, &tx, &Recorder::disabled()).is_err());
+    assert!(connect_sensor(&Transport::Stdout, &tx).is_err());
+}
+
+/// Set for the copy of the test binary run with redirected standard streams.
+const CHILD: &str = "FSSP_STANDARD_STREAMS";
+
+#[test]
+fn standard_streams_stay_with_the_links_once_the_terminal_is_taken() {
+    let name = "standard_streams_stay_with_the_links_once_the_terminal_is_taken";
+    if env::var(CHILD).is_ok() {
+        // G-code out of each position read, on the streams the links got
+        let (tx, rx) = mpsc::channel();
+        let (driver, link) = connect_driver(&Transport::Stdout, 0, &tx, &Recorder::disabled()).unwrap();
+        let _sensor = connect_sensor(&Transport::Stdin, &tx).unwrap();
+        let (_terminal, path) = pty();
+        take_terminal(&path).unwrap();
+        println!(":: Operator message");
+        match rx.recv_timeout(Duration::from_secs(5)) {
+            Ok(Message::Event(Event::Position { x, .. })) => {
+                driver.send(Command::MoveTo { x: Some(x), y: None, z: None, f: None }).unwrap();
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        drop(driver);
+        assert!(link.close(Duration::from_secs(1)));
+        return;
+    }
+
+    let mut child = process::Command::new(env::current_exe().unwrap())
+        .args(&[name, "--exact", "--nocapture"])
+        .env(CHILD, "1")
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.as_mut().unwrap().write_all(b"X:12.000 Y:0.000 Z:0.000\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    let output = String::from_utf8_lossy(&output.stdout);
+    assert!(output.lines().any(|line| line == "G1 X12"), "{}", output);
+    assert!(!output.contains("Operator message"), "{}", output);
+}
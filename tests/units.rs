@@ -0,0 +1,38 @@
+extern crate fssp_simulator;
+extern crate serde_json;
+
+use fssp_simulator::driver::command::Command;
+use fssp_simulator::frame::{Vector, World};
+use fssp_simulator::units::{DegreesPerMinute, Millimeters, MillimetersPerMinute};
+use std::time::Duration;
+
+#[test]
+fn rates_integrate_over_time() {
+    let covered = MillimetersPerMinute(6000.).over(Duration::from_millis(500));
+    assert_eq!(covered, Millimeters(50.));
+    assert_eq!(DegreesPerMinute(120.).per_second(), 2.);
+    assert_eq!(format!("{}", MillimetersPerMinute(6000.)), "6000 mm/min");
+}
+
+#[test]
+fn world_positions_become_machine_moves() {
+    let position: Vector<World, Millimeters> = Vector::new(Millimeters(1.), Millimeters(2.), Millimeters(3.));
+    let command = Command::move_to(position.to_machine(), MillimetersPerMinute(100.));
+    assert_eq!(
+        command,
+        Command::MoveTo {
+            x: Some(Millimeters(1.)),
+            y: Some(Millimeters(2.)),
+            z: Some(Millimeters(3.)),
+            f: Some(MillimetersPerMinute(100.)),
+        }
+    );
+}
+
+#[test]
+fn quantities_are_recorded_as_bare_numbers() {
+    let command = Command::MoveMotorTo { m: 1, l: Millimeters(2.5), f: None };
+    let json = serde_json::to_string(&command).unwrap();
+    assert_eq!(json, r#"{"MoveMotorTo":{"m":1,"l":2.5,"f":null}}"#);
+    assert_eq!(serde_json::from_str::<Command>(&json).unwrap(), command);
+}